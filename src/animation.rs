@@ -0,0 +1,379 @@
+use std::collections::HashMap;
+
+use cgmath::EuclideanSpace;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    camera::Camera,
+    material::{present, MaterialDefinition},
+    pixel_drawer::{Material, Transform},
+};
+
+// anything that can be blended between keys, which is numbers and vectors
+pub trait Animatable:
+    Copy
+    + std::ops::Add<Output = Self>
+    + std::ops::Sub<Output = Self>
+    + std::ops::Mul<f64, Output = Self>
+{
+}
+
+impl<T> Animatable for T where
+    T: Copy
+        + std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<f64, Output = T>
+{
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    // a cubic bezier between each pair of keys with its handles a third of the way along, pointing along the
+    // slope at each key. keys without a slope get one from their neighbours, and the first and last one ease in and out
+    Bezier,
+}
+
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(bound(deserialize = "T: Deserialize<'de>"))]
+pub struct Key<T> {
+    // in seconds
+    pub time: f64,
+    pub value: T,
+    #[serde(default, with = "present", skip_serializing_if = "Option::is_none")]
+    pub slope: Option<T>,
+}
+
+// a value that changes over time, before the first key and after the last one it holds still
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Track<T> {
+    #[serde(default = "default_interpolation")]
+    pub interpolation: Interpolation,
+    pub keys: Vec<Key<T>>,
+}
+
+fn default_interpolation() -> Interpolation {
+    Interpolation::Linear
+}
+
+impl<T: Animatable> Track<T> {
+    pub fn check(&self, name: &str) -> Result<(), String> {
+        if self.keys.is_empty() {
+            return Err(format!("the {} track has no keys", name));
+        }
+        if self.keys.windows(2).any(|k| k[0].time >= k[1].time) {
+            return Err(format!(
+                "the keys of the {} track aren't in time order",
+                name
+            ));
+        }
+        Ok(())
+    }
+
+    pub fn at(&self, time: f64) -> T {
+        let last = self.keys.len() - 1;
+        let next = self.keys.partition_point(|k| k.time <= time);
+        if next == 0 {
+            return self.keys[0].value;
+        }
+        if next > last {
+            return self.keys[last].value;
+        }
+
+        let (a, b) = (&self.keys[next - 1], &self.keys[next]);
+        let span = b.time - a.time;
+        let s = (time - a.time) / span;
+        match self.interpolation {
+            Interpolation::Linear => a.value + (b.value - a.value) * s,
+            Interpolation::Bezier => {
+                // as a hermite curve, which is the same thing as the bezier with those handles
+                let slope_a = self.slope(next - 1) * span;
+                let slope_b = self.slope(next) * span;
+                let s2 = s * s;
+                let s3 = s2 * s;
+                a.value * (2.0 * s3 - 3.0 * s2 + 1.0)
+                    + slope_a * (s3 - 2.0 * s2 + s)
+                    + b.value * (3.0 * s2 - 2.0 * s3)
+                    + slope_b * (s3 - s2)
+            }
+        }
+    }
+
+    fn slope(&self, index: usize) -> T {
+        let key = &self.keys[index];
+        if let Some(slope) = key.slope {
+            return slope;
+        }
+        if index == 0 || index == self.keys.len() - 1 {
+            return key.value * 0.0;
+        }
+        let (before, after) = (&self.keys[index - 1], &self.keys[index + 1]);
+        (after.value - before.value) * (1.0 / (after.time - before.time))
+    }
+}
+
+pub fn check_optional<T: Animatable>(track: &Option<Track<T>>, name: &str) -> Result<(), String> {
+    track.as_ref().map_or(Ok(()), |t| t.check(name))
+}
+
+// the parts of a Transform that move over time, the ones left out stay at their defaults
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct TransformTracks {
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub translation: Option<Track<cgmath::Vector3<f64>>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<Track<cgmath::Vector3<f64>>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub scale: Option<Track<f64>>,
+}
+
+impl TransformTracks {
+    pub fn check(&self) -> Result<(), String> {
+        check_optional(&self.translation, "translation")?;
+        check_optional(&self.rotation, "rotation")?;
+        check_optional(&self.scale, "scale")
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        let mut transform = Transform::default();
+        if let Some(track) = &self.translation {
+            transform.translation = track.at(time);
+        }
+        if let Some(track) = &self.rotation {
+            transform.rotation = track.at(time);
+        }
+        if let Some(track) = &self.scale {
+            transform.scale = track.at(time);
+        }
+        transform
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct CameraTracks {
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub position: Option<Track<cgmath::Vector3<f64>>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub rotation: Option<Track<cgmath::Vector3<f64>>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub fov: Option<Track<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub aperture: Option<Track<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub focal_distance: Option<Track<f64>>,
+}
+
+impl CameraTracks {
+    fn check(&self) -> Result<(), String> {
+        check_optional(&self.position, "camera position")?;
+        check_optional(&self.rotation, "camera rotation")?;
+        check_optional(&self.fov, "camera fov")?;
+        check_optional(&self.aperture, "camera aperture")?;
+        check_optional(&self.focal_distance, "camera focal_distance")
+    }
+
+    fn apply(&self, camera: &mut Camera, time: f64) {
+        if let Some(track) = &self.position {
+            camera.position = cgmath::Point3::from_vec(track.at(time));
+        }
+        if let Some(track) = &self.rotation {
+            camera.rotation = track.at(time);
+        }
+        if let Some(track) = &self.fov {
+            camera.fov = track.at(time);
+        }
+        if let Some(track) = &self.aperture {
+            camera.aperture = track.at(time);
+        }
+        if let Some(track) = &self.focal_distance {
+            camera.focal_distance = track.at(time);
+        }
+    }
+}
+
+// the material values that can be animated, they replace whatever the material says
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MaterialTracks {
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub color: Option<Track<cgmath::Vector3<f64>>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub emitance: Option<Track<cgmath::Vector3<f64>>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub metalness: Option<Track<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub roughness: Option<Track<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub ior: Option<Track<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub transmission: Option<Track<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub absorption: Option<Track<cgmath::Vector3<f64>>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub scattering: Option<Track<cgmath::Vector3<f64>>>,
+}
+
+impl MaterialTracks {
+    fn check(&self, name: &str) -> Result<(), String> {
+        let field = |field: &str| format!("{} {}", name, field);
+        check_optional(&self.color, &field("color"))?;
+        check_optional(&self.emitance, &field("emitance"))?;
+        check_optional(&self.metalness, &field("metalness"))?;
+        check_optional(&self.roughness, &field("roughness"))?;
+        check_optional(&self.ior, &field("ior"))?;
+        check_optional(&self.transmission, &field("transmission"))?;
+        check_optional(&self.absorption, &field("absorption"))?;
+        check_optional(&self.scattering, &field("scattering"))
+    }
+
+    // what a material ends up with, when definition is its definition, own its own tracks and base the tracks
+    // its base ends up with. the base's tracks only reach the fields the material leaves to its base
+    fn inherit(
+        own: Option<&MaterialTracks>,
+        base: Option<MaterialTracks>,
+        definition: &MaterialDefinition,
+    ) -> Option<MaterialTracks> {
+        fn pick<T: Clone, U>(
+            own: &Option<Track<T>>,
+            base: &Option<Track<T>>,
+            set: &Option<U>,
+        ) -> Option<Track<T>> {
+            own.clone()
+                .or_else(|| base.clone().filter(|_| set.is_none()))
+        }
+        if own.is_none() && base.is_none() {
+            return None;
+        }
+        let own = own.cloned().unwrap_or_default();
+        let base = base.unwrap_or_default();
+        Some(MaterialTracks {
+            color: pick(&own.color, &base.color, &definition.color),
+            emitance: pick(&own.emitance, &base.emitance, &definition.emitance),
+            metalness: pick(&own.metalness, &base.metalness, &definition.metalness),
+            roughness: pick(&own.roughness, &base.roughness, &definition.roughness),
+            ior: pick(&own.ior, &base.ior, &definition.ior),
+            transmission: pick(
+                &own.transmission,
+                &base.transmission,
+                &definition.transmission,
+            ),
+            absorption: pick(&own.absorption, &base.absorption, &definition.absorption),
+            scattering: pick(&own.scattering, &base.scattering, &definition.scattering),
+        })
+    }
+
+    fn apply(&self, material: &mut Material, time: f64) {
+        fn set<T: Animatable>(value: &mut T, track: &Option<Track<T>>, time: f64) {
+            if let Some(track) = track {
+                *value = track.at(time);
+            }
+        }
+        set(&mut material.color, &self.color, time);
+        set(&mut material.emitance, &self.emitance, time);
+        set(&mut material.metalness, &self.metalness, time);
+        set(&mut material.roughness, &self.roughness, time);
+        set(&mut material.ior, &self.ior, time);
+        set(&mut material.transmission, &self.transmission, time);
+        set(&mut material.absorption, &self.absorption, time);
+        set(&mut material.scattering, &self.scattering, time);
+    }
+}
+
+// everything in a scene that moves, except for objects which get wrapped in Animated right where they are
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct Animation {
+    pub fps: f64,
+    // how long the shutter stays open, in frames. things that move while it's open get blurred, 0 turns that off
+    pub shutter: f64,
+    // the frames a sequence renders when it isn't told otherwise, end isn't included
+    pub start: u32,
+    pub end: u32,
+    pub camera: CameraTracks,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub sky_color: Option<Track<cgmath::Vector3<f64>>>,
+    pub materials: HashMap<String, MaterialTracks>,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Self {
+            fps: 24.0,
+            shutter: 0.0,
+            start: 0,
+            end: 1,
+            camera: CameraTracks::default(),
+            sky_color: None,
+            materials: HashMap::new(),
+        }
+    }
+}
+
+impl Animation {
+    pub fn check(&self, materials: &HashMap<String, Material>) -> Result<(), String> {
+        if self.fps <= 0.0 {
+            return Err("the animation's fps has to be above 0".to_string());
+        }
+        if self.shutter < 0.0 {
+            return Err("the animation's shutter can't be negative".to_string());
+        }
+        self.camera.check()?;
+        check_optional(&self.sky_color, "sky_color")?;
+        for (name, tracks) in &self.materials {
+            if !materials.contains_key(name) {
+                return Err(format!("animation for unknown material \"{}\"", name));
+            }
+            tracks.check(name)?;
+        }
+        Ok(())
+    }
+
+    pub fn time_of_frame(&self, frame: u32) -> f64 {
+        frame as f64 / self.fps
+    }
+
+    // these take what the scene says and give back what it is at time
+    pub fn camera_at(&self, mut camera: Camera, time: f64) -> Camera {
+        self.camera.apply(&mut camera, time);
+        camera
+    }
+
+    pub fn sky_color_at(&self, sky_color: cgmath::Vector3<f64>, time: f64) -> cgmath::Vector3<f64> {
+        self.sky_color
+            .as_ref()
+            .map_or(sky_color, |track| track.at(time))
+    }
+
+    // materials that have an animated one as their base (or its base and so on) get animated along with it,
+    // this works out their tracks once so looking them up stays a single get. the bases have to be resolvable already
+    pub fn inherit_material_tracks(&mut self, definitions: &HashMap<String, MaterialDefinition>) {
+        fn tracks_of(
+            own: &HashMap<String, MaterialTracks>,
+            name: &str,
+            definition: &MaterialDefinition,
+            definitions: &HashMap<String, MaterialDefinition>,
+        ) -> Option<MaterialTracks> {
+            let base = definition
+                .base
+                .as_ref()
+                .and_then(|base| tracks_of(own, base, definitions.get(base)?, definitions));
+            MaterialTracks::inherit(own.get(name), base, definition)
+        }
+        self.materials = definitions
+            .iter()
+            .filter_map(|(name, definition)| {
+                tracks_of(&self.materials, name, definition, definitions)
+                    .map(|tracks| (name.clone(), tracks))
+            })
+            .collect();
+    }
+
+    pub fn animate_material(&self, name: &str, material: &mut Material, time: f64) {
+        if let Some(tracks) = self.materials.get(name) {
+            tracks.apply(material, time);
+        }
+    }
+}
@@ -0,0 +1,263 @@
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::{
+    pixel_drawer::{render_pixel_aovs, World},
+    tone_mapping::{linear_to_srgb, ToneMapping},
+};
+
+// the extra pictures a render can come with, all taken from the first thing each ray hits
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Aov {
+    // how far along the ray the hit is
+    Depth,
+    Normal,
+    Albedo,
+    Emission,
+    MaterialId,
+    // the index of the top level object in the scene
+    ObjectId,
+    // how many steps the march took to get there
+    Steps,
+}
+
+impl Aov {
+    pub const ALL: [Aov; 7] = [
+        Aov::Depth,
+        Aov::Normal,
+        Aov::Albedo,
+        Aov::Emission,
+        Aov::MaterialId,
+        Aov::ObjectId,
+        Aov::Steps,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|aov| aov.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Aov::Depth => "depth",
+            Aov::Normal => "normal",
+            Aov::Albedo => "albedo",
+            Aov::Emission => "emission",
+            Aov::MaterialId => "material_id",
+            Aov::ObjectId => "object_id",
+            Aov::Steps => "steps",
+        }
+    }
+
+    // the channels it gets in an exr, named the way compositors expect them
+    fn channels(self) -> &'static [&'static str] {
+        match self {
+            Aov::Depth => &["Z"],
+            Aov::Normal => &["X", "Y", "Z"],
+            Aov::Albedo | Aov::Emission => &["R", "G", "B"],
+            Aov::MaterialId | Aov::ObjectId | Aov::Steps => &["X"],
+        }
+    }
+}
+
+// a comma separated list of aov names, or all of them
+pub fn parse_list(list: &str) -> Result<Vec<Aov>, String> {
+    if list == "all" {
+        return Ok(Aov::ALL.to_vec());
+    }
+    list.split(',')
+        .map(|name| Aov::from_name(name.trim()).ok_or(format!("unknown aov {}", name)))
+        .collect()
+}
+
+// what one pixel's aovs are, misses have an infinite depth and the sky as their emission
+#[derive(Clone, Copy, Debug)]
+pub struct Aovs {
+    pub depth: f64,
+    pub normal: cgmath::Vector3<f64>,
+    pub albedo: cgmath::Vector3<f64>,
+    pub emission: cgmath::Vector3<f64>,
+    pub material_id: Option<u32>,
+    pub object_id: Option<u32>,
+    pub steps: f64,
+}
+
+impl Default for Aovs {
+    fn default() -> Self {
+        Self {
+            depth: f64::INFINITY,
+            normal: cgmath::vec3(0.0, 0.0, 0.0),
+            albedo: cgmath::vec3(0.0, 0.0, 0.0),
+            emission: cgmath::vec3(0.0, 0.0, 0.0),
+            material_id: None,
+            object_id: None,
+            steps: 0.0,
+        }
+    }
+}
+
+impl Aovs {
+    // averages the samples of a pixel. ids can't be averaged so they come from the first sample,
+    // and depth is the closest one so edges don't end up halfway to infinity
+    pub fn average(samples: &[Aovs]) -> Aovs {
+        let Some(first) = samples.first() else {
+            return Aovs::default();
+        };
+        let count = samples.len() as f64;
+        let sum = |field: fn(&Aovs) -> cgmath::Vector3<f64>| {
+            samples
+                .iter()
+                .map(field)
+                .fold(cgmath::vec3(0.0, 0.0, 0.0), |a, b| a + b)
+                / count
+        };
+        Aovs {
+            depth: samples
+                .iter()
+                .map(|s| s.depth)
+                .fold(f64::INFINITY, f64::min),
+            normal: sum(|s| s.normal),
+            albedo: sum(|s| s.albedo),
+            emission: sum(|s| s.emission),
+            material_id: first.material_id,
+            object_id: first.object_id,
+            steps: samples.iter().map(|s| s.steps).sum::<f64>() / count,
+        }
+    }
+
+    // the raw values of one aov
+    fn values(&self, aov: Aov) -> Vec<f64> {
+        let id = |id: Option<u32>| id.map_or(-1.0, |id| id as f64);
+        match aov {
+            Aov::Depth => vec![self.depth],
+            Aov::Normal => vec![self.normal.x, self.normal.y, self.normal.z],
+            Aov::Albedo => vec![self.albedo.x, self.albedo.y, self.albedo.z],
+            Aov::Emission => vec![self.emission.x, self.emission.y, self.emission.z],
+            Aov::MaterialId => vec![id(self.material_id)],
+            Aov::ObjectId => vec![id(self.object_id)],
+            Aov::Steps => vec![self.steps],
+        }
+    }
+}
+
+// the aovs of every pixel, averaged over samples rays like the picture itself
+pub fn render(world: &World, (width, height): (u32, u32), samples: u32) -> Vec<Aovs> {
+    (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let samples: Vec<_> = (0..samples.max(1))
+                .map(|_| render_pixel_aovs((width, height), index, world))
+                .collect();
+            Aovs::average(&samples)
+        })
+        .collect()
+}
+
+// a color that's easy to tell apart from the ones next to it, for showing ids
+fn id_color(id: Option<u32>) -> cgmath::Vector3<f64> {
+    let Some(id) = id else {
+        return cgmath::vec3(0.0, 0.0, 0.0);
+    };
+    let hash = (id + 1).wrapping_mul(2654435761);
+    cgmath::vec3(hash >> 24, (hash >> 16) & 255, (hash >> 8) & 255).map(|c| c as f64 / 255.0)
+}
+
+// turns an aov into something that can be looked at, with every channel between 0 and 1
+pub fn visualize(
+    aov: Aov,
+    pixels: &[Aovs],
+    tone_mapping: &ToneMapping,
+) -> Vec<cgmath::Vector3<f64>> {
+    let max = |field: fn(&Aovs) -> f64| {
+        pixels
+            .iter()
+            .map(field)
+            .filter(|v| v.is_finite())
+            .fold(0.0, f64::max)
+    };
+    match aov {
+        Aov::Depth => {
+            let max = max(|p| p.depth);
+            pixels
+                .iter()
+                .map(|p| {
+                    let v = if p.depth.is_finite() && max > 0.0 {
+                        1.0 - p.depth / max
+                    } else {
+                        0.0
+                    };
+                    cgmath::vec3(v, v, v)
+                })
+                .collect()
+        }
+        Aov::Normal => pixels
+            .iter()
+            .map(|p| p.normal * 0.5 + cgmath::vec3(0.5, 0.5, 0.5))
+            .collect(),
+        Aov::Albedo => pixels
+            .iter()
+            .map(|p| p.albedo.map(|c| linear_to_srgb(c.clamp(0.0, 1.0))))
+            .collect(),
+        Aov::Emission => pixels
+            .iter()
+            .map(|p| tone_mapping.map(p.emission))
+            .collect(),
+        Aov::MaterialId => pixels.iter().map(|p| id_color(p.material_id)).collect(),
+        Aov::ObjectId => pixels.iter().map(|p| id_color(p.object_id)).collect(),
+        Aov::Steps => {
+            let max = max(|p| p.steps).max(1.0);
+            pixels
+                .iter()
+                .map(|p| {
+                    let v = p.steps / max;
+                    cgmath::vec3(v, v, v)
+                })
+                .collect()
+        }
+    }
+}
+
+// one exr with the picture itself as R, G and B and every aov as its own layer
+pub fn save_exr(
+    path: &Path,
+    beauty: &[cgmath::Vector3<f64>],
+    pixels: &[Aovs],
+    aovs: &[Aov],
+    (width, height): (u32, u32),
+) -> Result<(), String> {
+    use exr::prelude::*;
+
+    let channel = |name: String, values: Vec<f32>| {
+        AnyChannel::new(Text::from(name.as_str()), FlatSamples::F32(values))
+    };
+    let mut channels: Vec<_> = ["R", "G", "B"]
+        .into_iter()
+        .enumerate()
+        .map(|(i, name)| {
+            channel(
+                name.to_string(),
+                beauty.iter().map(|p| p[i] as f32).collect(),
+            )
+        })
+        .collect();
+    for &aov in aovs {
+        let values: Vec<_> = pixels.iter().map(|p| p.values(aov)).collect();
+        for (i, name) in aov.channels().iter().enumerate() {
+            channels.push(channel(
+                format!("{}.{}", aov.name(), name),
+                values.iter().map(|v| v[i] as f32).collect(),
+            ));
+        }
+    }
+
+    let layer = Layer::new(
+        (width as usize, height as usize),
+        LayerAttributes::named(""),
+        Encoding::default(),
+        AnyChannels::sort(channels.into()),
+    );
+    Image::from_layer(layer)
+        .write()
+        .to_file(path)
+        .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
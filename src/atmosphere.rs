@@ -0,0 +1,82 @@
+use cgmath::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// fog that fills all the space outside of objects. the sky is treated as lighting the fog evenly from everywhere,
+// and anything else (lights, lit objects) reaches it by actually scattering rays around in it
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct Atmosphere {
+    // scattering per unit of distance everywhere
+    pub density: f64,
+    // extra scattering at base_height, it falls off exponentially going up
+    pub height_density: f64,
+    pub height_falloff: f64,
+    pub base_height: f64,
+    // the y axis of the camera points down, so up is -y unless a scene says otherwise
+    pub up: cgmath::Vector3<f64>,
+    // how much of the scattered light survives, per color
+    pub color: cgmath::Vector3<f64>,
+    // henyey-greenstein g for the fog
+    pub anisotropy: f64,
+}
+
+impl Default for Atmosphere {
+    fn default() -> Self {
+        Self {
+            density: 0.0,
+            height_density: 0.0,
+            height_falloff: 1.0,
+            base_height: 0.0,
+            up: cgmath::vec3(0.0, -1.0, 0.0),
+            color: cgmath::vec3(1.0, 1.0, 1.0),
+            anisotropy: 0.0,
+        }
+    }
+}
+
+impl Atmosphere {
+    // picks where along the segment the ray scatters, if it does at all.
+    // the uniform and the height part are sampled on their own and the closer one wins,
+    // which is the same as sampling their sum
+    pub fn sample_scatter<T: rand::Rng>(
+        &self,
+        from: cgmath::Point3<f64>,
+        direction: cgmath::Vector3<f64>,
+        max_distance: f64,
+        rand: &mut T,
+    ) -> Option<f64> {
+        let mut closest = f64::INFINITY;
+
+        if self.density > 0.0 {
+            closest = -(1.0 - rand.gen::<f64>()).ln() / self.density;
+        }
+
+        if self.height_density > 0.0 {
+            let up = self.up.normalize();
+            let start_height = from.to_vec().dot(up) - self.base_height;
+            let start_density = self.height_density * (-self.height_falloff * start_height).exp();
+            // how quickly the density changes along the ray
+            let rate = self.height_falloff * direction.dot(up);
+            let optical_depth = -(1.0 - rand.gen::<f64>()).ln();
+
+            let distance = if rate.abs() < 1e-9 {
+                optical_depth / start_density
+            } else {
+                let remaining = 1.0 - optical_depth * rate / start_density;
+                if remaining > 0.0 {
+                    -remaining.ln() / rate
+                } else {
+                    // the ray heads up into air so thin it never scatters
+                    f64::INFINITY
+                }
+            };
+            closest = closest.min(distance);
+        }
+
+        if closest < max_distance {
+            Some(closest)
+        } else {
+            None
+        }
+    }
+}
@@ -0,0 +1,437 @@
+use cgmath::prelude::*;
+
+use crate::pixel_drawer::Material;
+
+static BLACK: cgmath::Vector3<f64> = cgmath::vec3(0.0, 0.0, 0.0);
+static WHITE: cgmath::Vector3<f64> = cgmath::vec3(1.0, 1.0, 1.0);
+
+// below this the ggx lobe is so sharp that the float math falls apart, so treat it as a near perfect mirror
+static MIN_ROUGHNESS: f64 = 0.01;
+
+// everything in here works in a local space where the normal is +y and the tangent is +x
+pub static LOCAL_NORMAL: cgmath::Vector3<f64> = cgmath::vec3(0.0, 1.0, 0.0);
+
+pub struct Frame {
+    tangent: cgmath::Vector3<f64>,
+    normal: cgmath::Vector3<f64>,
+    bitangent: cgmath::Vector3<f64>,
+}
+
+impl Frame {
+    // the tangent only needs to point roughly along the surface, it gets straightened out here
+    pub fn new(normal: cgmath::Vector3<f64>, tangent: cgmath::Vector3<f64>) -> Self {
+        let mut tangent = tangent - normal * normal.dot(tangent);
+        if tangent.magnitude2() < 1e-12 {
+            let other = if normal.x.abs() < 0.9 {
+                cgmath::vec3(1.0, 0.0, 0.0)
+            } else {
+                cgmath::vec3(0.0, 0.0, 1.0)
+            };
+            tangent = other - normal * normal.dot(other);
+        }
+        let tangent = tangent.normalize();
+        Self {
+            tangent,
+            normal,
+            bitangent: tangent.cross(normal),
+        }
+    }
+
+    pub fn to_local(&self, v: cgmath::Vector3<f64>) -> cgmath::Vector3<f64> {
+        cgmath::vec3(
+            v.dot(self.tangent),
+            v.dot(self.normal),
+            v.dot(self.bitangent),
+        )
+    }
+
+    pub fn to_world(&self, v: cgmath::Vector3<f64>) -> cgmath::Vector3<f64> {
+        self.tangent * v.x + self.normal * v.y + self.bitangent * v.z
+    }
+}
+
+// alpha is the usual roughness squared, anisotropy stretches it along the tangent like the disney brdf does
+fn alphas(roughness: f64, anisotropy: f64) -> (f64, f64) {
+    let alpha = roughness.max(MIN_ROUGHNESS).powi(2);
+    let aspect = (1.0 - 0.9 * anisotropy.clamp(0.0, 1.0)).sqrt();
+    (alpha / aspect, alpha * aspect)
+}
+
+fn distribution_ggx(halfway: cgmath::Vector3<f64>, alpha_t: f64, alpha_b: f64) -> f64 {
+    if halfway.y <= 0.0 {
+        return 0.0;
+    }
+    let x = halfway.x / alpha_t;
+    let z = halfway.z / alpha_b;
+    let denom = x * x + halfway.y * halfway.y + z * z;
+
+    1.0 / (std::f64::consts::PI * alpha_t * alpha_b * denom * denom)
+}
+
+fn geometry_smith_g1(dir: cgmath::Vector3<f64>, alpha_t: f64, alpha_b: f64) -> f64 {
+    let cos2 = dir.y * dir.y;
+    if cos2 <= 0.0 {
+        return 0.0;
+    }
+    let projected = (alpha_t * dir.x).powi(2) + (alpha_b * dir.z).powi(2);
+    let lambda = (-1.0 + (1.0 + projected / cos2).sqrt()) / 2.0;
+    1.0 / (1.0 + lambda)
+}
+
+fn geometry_smith(
+    view: cgmath::Vector3<f64>,
+    light: cgmath::Vector3<f64>,
+    alpha_t: f64,
+    alpha_b: f64,
+) -> f64 {
+    geometry_smith_g1(view, alpha_t, alpha_b) * geometry_smith_g1(light, alpha_t, alpha_b)
+}
+
+fn fresnel_schlick(cos_theta: f64, f0: cgmath::Vector3<f64>) -> cgmath::Vector3<f64> {
+    f0 + f0.map(|v| 1.0 - v) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+fn fresnel_schlick_scalar(cos_theta: f64, f0: f64) -> f64 {
+    f0 + (1.0 - f0) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
+}
+
+pub fn average(v: cgmath::Vector3<f64>) -> f64 {
+    (v.x + v.y + v.z) / 3.0
+}
+
+fn max_component(v: cgmath::Vector3<f64>) -> f64 {
+    v.x.max(v.y).max(v.z)
+}
+
+fn reflect(incoming: cgmath::Vector3<f64>, normal: cgmath::Vector3<f64>) -> cgmath::Vector3<f64> {
+    incoming - normal * 2.0 * normal.dot(incoming)
+}
+
+fn sample_cosine_hemisphere<T: rand::Rng>(rand: &mut T) -> cgmath::Vector3<f64> {
+    let u1: f64 = rand.gen();
+    let u2: f64 = rand.gen();
+    let r = u1.sqrt();
+    let (z, x) = (2.0 * std::f64::consts::PI * u2).sin_cos();
+    cgmath::vec3(x * r, (1.0 - u1).max(0.0).sqrt(), z * r)
+}
+
+// samples slopes from the unit roughness distribution and stretches them, which gives halfways with a pdf of D * cos
+fn sample_ggx_halfway<T: rand::Rng>(
+    alpha_t: f64,
+    alpha_b: f64,
+    rand: &mut T,
+) -> cgmath::Vector3<f64> {
+    let u1: f64 = rand.gen();
+    let u2: f64 = rand.gen();
+    let slope = (u1 / (1.0 - u1)).sqrt();
+    let (z, x) = (2.0 * std::f64::consts::PI * u2).sin_cos();
+    cgmath::vec3(x * slope * alpha_t, 1.0, z * slope * alpha_b).normalize()
+}
+
+fn diffuse_pdf(light: cgmath::Vector3<f64>) -> f64 {
+    light.y.max(0.0) / std::f64::consts::PI
+}
+
+fn specular_pdf(
+    view: cgmath::Vector3<f64>,
+    light: cgmath::Vector3<f64>,
+    alpha_t: f64,
+    alpha_b: f64,
+) -> f64 {
+    let halfway = (view + light).normalize();
+    let v_dot_h = view.dot(halfway);
+    if v_dot_h <= 0.0 {
+        return 0.0;
+    }
+    distribution_ggx(halfway, alpha_t, alpha_b) * halfway.y.max(0.0) / (4.0 * v_dot_h)
+}
+
+// "charlie" cloth sheen from Estevez and Kulla with Ashikhmin's visibility term
+fn distribution_charlie(halfway: cgmath::Vector3<f64>, roughness: f64) -> f64 {
+    let inv = 1.0 / roughness;
+    let sin2 = (1.0 - halfway.y * halfway.y).max(0.0);
+    (2.0 + inv) * sin2.powf(inv * 0.5) / (2.0 * std::f64::consts::PI)
+}
+
+fn visibility_ashikhmin(n_dot_v: f64, n_dot_l: f64) -> f64 {
+    1.0 / (4.0 * (n_dot_l + n_dot_v - n_dot_l * n_dot_v))
+}
+
+static SHEEN_ROUGHNESSES: [f64; 7] = [0.1, 0.25, 0.4, 0.55, 0.7, 0.85, 1.0];
+// how much of the light the sheen lobe reflects, for each roughness above and n dot v going from 0 to 1 in eighths.
+// integrated numerically, the grazing values at low roughness go over 1 because the visibility term isn't exact
+static SHEEN_ALBEDO: [[f64; 9]; 7] = [
+    [
+        1.352, 0.703, 0.424, 0.255, 0.148, 0.080, 0.038, 0.014, 0.002,
+    ],
+    [
+        0.937, 0.596, 0.431, 0.316, 0.230, 0.162, 0.108, 0.065, 0.031,
+    ],
+    [
+        0.800, 0.546, 0.420, 0.329, 0.257, 0.198, 0.147, 0.103, 0.065,
+    ],
+    [
+        0.730, 0.517, 0.411, 0.333, 0.271, 0.218, 0.171, 0.129, 0.092,
+    ],
+    [
+        0.687, 0.497, 0.404, 0.335, 0.279, 0.230, 0.188, 0.149, 0.112,
+    ],
+    [
+        0.658, 0.484, 0.398, 0.335, 0.284, 0.239, 0.199, 0.163, 0.129,
+    ],
+    [
+        0.636, 0.473, 0.394, 0.335, 0.287, 0.246, 0.208, 0.174, 0.141,
+    ],
+];
+
+fn sheen_albedo(n_dot_v: f64, roughness: f64) -> f64 {
+    let r = roughness.clamp(SHEEN_ROUGHNESSES[0], 1.0);
+    let row = SHEEN_ROUGHNESSES
+        .iter()
+        .position(|&x| x >= r)
+        .unwrap_or(SHEEN_ROUGHNESSES.len() - 1)
+        .max(1);
+    let row_t =
+        (r - SHEEN_ROUGHNESSES[row - 1]) / (SHEEN_ROUGHNESSES[row] - SHEEN_ROUGHNESSES[row - 1]);
+
+    let column = n_dot_v.clamp(0.0, 1.0) * 8.0;
+    let column_low = (column.floor() as usize).min(7);
+    let column_t = column - column_low as f64;
+
+    let at = |row: usize| {
+        SHEEN_ALBEDO[row][column_low] * (1.0 - column_t)
+            + SHEEN_ALBEDO[row][column_low + 1] * column_t
+    };
+    (at(row - 1) * (1.0 - row_t) + at(row) * row_t).min(1.0)
+}
+
+fn base_reflectivity(material: &Material) -> cgmath::Vector3<f64> {
+    let ior = material.specular_ior.unwrap_or(material.ior);
+    let f0 = ((ior - 1.0) / (ior + 1.0)).powi(2);
+    let luminance = material.color.dot(cgmath::vec3(0.2126, 0.7152, 0.0722));
+    let tint = if luminance > 0.0 {
+        material.color / luminance
+    } else {
+        WHITE
+    };
+    let dielectric =
+        (WHITE.lerp(tint, material.specular_tint.clamp(0.0, 1.0)) * f0).map(|x| x.clamp(0.0, 1.0));
+    dielectric.lerp(material.color, material.metalness)
+}
+
+// the opaque part of a material is a clearcoat on top of a sheen on top of the diffuse and specular base,
+// each layer takes away what it reflects from the ones below it
+struct Layers {
+    clearcoat: f64,
+    sheen: f64,
+    base: f64,
+}
+
+fn layer_weights(n_dot_v: f64, material: &Material) -> Layers {
+    let clearcoat = material.clearcoat.clamp(0.0, 1.0) * fresnel_schlick_scalar(n_dot_v, 0.04);
+    let sheen = material.sheen.clamp(0.0, 1.0)
+        * max_component(material.sheen_color)
+        * sheen_albedo(n_dot_v, material.sheen_roughness);
+    Layers {
+        clearcoat,
+        sheen,
+        base: (1.0 - clearcoat) * (1.0 - sheen),
+    }
+}
+
+fn evaluate_brdf(
+    view: cgmath::Vector3<f64>,
+    light: cgmath::Vector3<f64>,
+    material: &Material,
+) -> cgmath::Vector3<f64> {
+    let n_dot_v = view.y;
+    let n_dot_l = light.y;
+    if n_dot_v <= 0.0 || n_dot_l <= 0.0 {
+        return BLACK;
+    }
+    let halfway = (view + light).normalize();
+    let v_dot_h = view.dot(halfway).max(0.0);
+    let layers = layer_weights(n_dot_v, material);
+
+    let (alpha_t, alpha_b) = alphas(material.roughness, material.specular_anisotropy);
+    let f0 = base_reflectivity(material);
+    let f = fresnel_schlick(v_dot_h, f0);
+    let d = distribution_ggx(halfway, alpha_t, alpha_b);
+    let g = geometry_smith(view, light, alpha_t, alpha_b);
+    let specular = f * (d * g / (4.0 * n_dot_v * n_dot_l));
+
+    // whatever the specular layer reflects towards the viewer can't also make it to the diffuse layer
+    let k_d = fresnel_schlick(n_dot_v, f0).map(|x| 1.0 - x) * (1.0 - material.metalness);
+    let base = k_d.mul_element_wise(material.color) / std::f64::consts::PI + specular;
+
+    let mut result = base * layers.base;
+
+    if material.sheen > 0.0 {
+        let roughness = material.sheen_roughness.clamp(0.07, 1.0);
+        result += material.sheen_color
+            * (material.sheen.clamp(0.0, 1.0)
+                * distribution_charlie(halfway, roughness)
+                * visibility_ashikhmin(n_dot_v, n_dot_l)
+                * (1.0 - layers.clearcoat));
+    }
+
+    if material.clearcoat > 0.0 {
+        let (alpha, _) = alphas(material.clearcoat_roughness, 0.0);
+        let coat = material.clearcoat.clamp(0.0, 1.0)
+            * fresnel_schlick_scalar(v_dot_h, 0.04)
+            * distribution_ggx(halfway, alpha, alpha)
+            * geometry_smith(view, light, alpha, alpha)
+            / (4.0 * n_dot_v * n_dot_l);
+        result += WHITE * coat;
+    }
+
+    result
+}
+
+pub struct BrdfSample {
+    pub direction: cgmath::Vector3<f64>,
+    // brdf * cos / pdf, what the incoming light along direction gets multiplied by
+    pub weight: cgmath::Vector3<f64>,
+}
+
+// picks one of the sampling strategies based on roughly how much energy its lobes carry, samples it,
+// and weights the result by the pdf of the whole mixture
+fn sample_brdf<T: rand::Rng>(
+    view: cgmath::Vector3<f64>,
+    material: &Material,
+    rand: &mut T,
+) -> Option<BrdfSample> {
+    let n_dot_v = view.y.max(0.0);
+    let layers = layer_weights(n_dot_v, material);
+    let f = fresnel_schlick(n_dot_v, base_reflectivity(material));
+
+    // the sheen is broad enough that sampling the cosine for it is fine
+    let cosine_weight = layers.base
+        * average(f.map(|x| 1.0 - x).mul_element_wise(material.color))
+        * (1.0 - material.metalness)
+        + layers.sheen * (1.0 - layers.clearcoat);
+    let specular_weight = layers.base * average(f);
+    let clearcoat_weight = layers.clearcoat;
+    let total = cosine_weight + specular_weight + clearcoat_weight;
+    let (cosine_chance, specular_chance) = if total > 0.0 {
+        (cosine_weight / total, specular_weight / total)
+    } else {
+        (0.0, 1.0)
+    };
+    let clearcoat_chance = (1.0 - cosine_chance - specular_chance).max(0.0);
+
+    let (alpha_t, alpha_b) = alphas(material.roughness, material.specular_anisotropy);
+    let (coat_alpha, _) = alphas(material.clearcoat_roughness, 0.0);
+
+    let pick: f64 = rand.gen();
+    let direction = if pick < cosine_chance {
+        sample_cosine_hemisphere(rand)
+    } else if pick < cosine_chance + specular_chance {
+        reflect(-view, sample_ggx_halfway(alpha_t, alpha_b, rand))
+    } else {
+        reflect(-view, sample_ggx_halfway(coat_alpha, coat_alpha, rand))
+    };
+    if direction.y <= 0.0 {
+        return None;
+    }
+
+    let mut pdf = cosine_chance * diffuse_pdf(direction)
+        + specular_chance * specular_pdf(view, direction, alpha_t, alpha_b);
+    if clearcoat_chance > 0.0 {
+        pdf += clearcoat_chance * specular_pdf(view, direction, coat_alpha, coat_alpha);
+    }
+    if pdf <= 0.0 {
+        return None;
+    }
+
+    Some(BrdfSample {
+        direction,
+        weight: evaluate_brdf(view, direction, material) * (direction.y / pdf),
+    })
+}
+
+// eta is the ior on the side the light comes from divided by the ior of the side it goes into
+fn fresnel_dielectric(cos_incoming: f64, eta: f64) -> f64 {
+    let sin2_transmitted = eta * eta * (1.0 - cos_incoming * cos_incoming);
+    if sin2_transmitted >= 1.0 {
+        return 1.0;
+    }
+    let cos_transmitted = (1.0 - sin2_transmitted).sqrt();
+    let r_s = (eta * cos_incoming - cos_transmitted) / (eta * cos_incoming + cos_transmitted);
+    let r_p = (cos_incoming - eta * cos_transmitted) / (cos_incoming + eta * cos_transmitted);
+    (r_s * r_s + r_p * r_p) / 2.0
+}
+
+fn refract(
+    incoming: cgmath::Vector3<f64>,
+    normal: cgmath::Vector3<f64>,
+    eta: f64,
+) -> Option<cgmath::Vector3<f64>> {
+    let cos_incoming = -incoming.dot(normal);
+    let sin2_transmitted = eta * eta * (1.0 - cos_incoming * cos_incoming);
+    if sin2_transmitted >= 1.0 {
+        return None;
+    }
+    Some(incoming * eta + normal * (eta * cos_incoming - (1.0 - sin2_transmitted).sqrt()))
+}
+
+// rough glass from "Microfacet Models for Refraction through Rough Surfaces" (Walter et al.),
+// the microfacet normal is sampled from the ggx distribution and fresnel picks reflection or refraction,
+// which makes most of the terms cancel out of the weight
+fn sample_dielectric<T: rand::Rng>(
+    view: cgmath::Vector3<f64>,
+    material: &Material,
+    eta: f64,
+    rand: &mut T,
+) -> Option<BrdfSample> {
+    if eta == 1.0 {
+        // nothing to bend the light, like the edge of a cloud of smoke
+        return Some(BrdfSample {
+            direction: -view,
+            weight: material.color,
+        });
+    }
+    let (alpha_t, alpha_b) = alphas(material.roughness, material.specular_anisotropy);
+    let halfway = sample_ggx_halfway(alpha_t, alpha_b, rand);
+    let v_dot_h = view.dot(halfway);
+    if v_dot_h <= 0.0 || view.y <= 0.0 {
+        return None;
+    }
+
+    let fresnel = fresnel_dielectric(v_dot_h, eta);
+    let (direction, tint) = if rand.gen::<f64>() < fresnel {
+        (reflect(-view, halfway), WHITE)
+    } else {
+        (refract(-view, halfway, eta)?, material.color)
+    };
+    // reflections have to stay above the surface and refractions have to go through it
+    if (direction.y > 0.0) != (direction.dot(halfway) > 0.0) {
+        return None;
+    }
+
+    let flipped = cgmath::vec3(direction.x, direction.y.abs(), direction.z);
+    let g = geometry_smith(view, flipped, alpha_t, alpha_b);
+    Some(BrdfSample {
+        direction,
+        weight: tint * (g * v_dot_h / (view.y * halfway.y)),
+    })
+}
+
+// materials are a mix of an opaque part and a glass part, one of them gets picked at random based on how much of it there is
+pub fn sample_bsdf<T: rand::Rng>(
+    view: cgmath::Vector3<f64>,
+    material: &Material,
+    eta: f64,
+    rand: &mut T,
+) -> Option<BrdfSample> {
+    let glass_chance = (1.0 - material.metalness) * material.transmission;
+    if rand.gen::<f64>() < glass_chance {
+        return sample_dielectric(view, material, eta, rand);
+    }
+    let opaque = Material {
+        metalness: material.metalness / (1.0 - glass_chance),
+        transmission: 0.0,
+        ..material.clone()
+    };
+    sample_brdf(view, &opaque, rand)
+}
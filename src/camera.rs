@@ -0,0 +1,205 @@
+use cgmath::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::march::Footprint;
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+pub enum Projection {
+    Perspective,
+    // all rays go straight ahead, width is how much of the scene fits across the picture
+    Orthographic { width: f64 },
+    // equidistant, the fov is the angle across the circle in the middle of the picture and can go past 180.
+    // outside the circle is black
+    Fisheye,
+    // the whole sphere around the camera, all the way around across the picture and from straight up
+    // to straight down along it, so it fits a picture twice as wide as it's tall
+    Equirectangular,
+}
+
+// where the picture is taken from. by default it sits at the origin looking down +z, with +y pointing down the image
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct Camera {
+    pub position: cgmath::Point3<f64>,
+    // in degrees, around x then y then z like Transform
+    pub rotation: cgmath::Vector3<f64>,
+    // across the width of the image, in degrees
+    pub fov: f64,
+    // radius of the lens, 0 keeps everything sharp
+    pub aperture: f64,
+    // how far in front of the camera things are in focus
+    pub focal_distance: f64,
+    // sets focal_distance to whatever is in the middle of the picture
+    pub autofocus: bool,
+    // the number of blades the aperture is made of, below 3 it's a perfect circle.
+    // the blades give out of focus highlights their shape
+    pub blades: u32,
+    // in degrees
+    pub blade_rotation: f64,
+    pub projection: Projection,
+    // renders the picture twice next to each other, for the left eye and then the right one
+    pub stereo: bool,
+    // how far apart the eyes are
+    pub eye_separation: f64,
+}
+
+impl Default for Camera {
+    fn default() -> Self {
+        Self {
+            position: cgmath::point3(0.0, 0.0, 0.0),
+            rotation: cgmath::vec3(0.0, 0.0, 0.0),
+            fov: 90.0,
+            aperture: 0.0,
+            focal_distance: 1.0,
+            autofocus: false,
+            blades: 0,
+            blade_rotation: 0.0,
+            projection: Projection::Perspective,
+            stereo: false,
+            eye_separation: 0.065,
+        }
+    }
+}
+
+impl Camera {
+    fn rotation_matrix(&self) -> cgmath::Matrix3<f64> {
+        cgmath::Matrix3::from(cgmath::Euler::new(
+            cgmath::Deg(self.rotation.x),
+            cgmath::Deg(self.rotation.y),
+            cgmath::Deg(self.rotation.z),
+        ))
+    }
+
+    // straight out of the middle of the lens
+    pub fn forward(&self) -> cgmath::Vector3<f64> {
+        self.rotation_matrix() * cgmath::vec3(0.0, 0.0, 1.0)
+    }
+
+    // x and y go from -1 to 1 across the width of the image, y can go further on tall images.
+    // gives nothing for the parts of the picture the projection doesn't cover
+    pub fn ray<T: rand::Rng>(
+        &self,
+        x: f64,
+        y: f64,
+        rand: &mut T,
+    ) -> Option<(cgmath::Point3<f64>, cgmath::Vector3<f64>)> {
+        // each eye gets half of the picture, with the same shape a whole one would have
+        let (x, y, eye) = match (self.stereo, x < 0.0) {
+            (false, _) => (x, y, 0.0),
+            (true, true) => (x * 2.0 + 1.0, y * 2.0, -0.5),
+            (true, false) => (x * 2.0 - 1.0, y * 2.0, 0.5),
+        };
+
+        // in the space of the camera, along with the way the eyes are apart for that ray
+        let right = cgmath::vec3(1.0, 0.0, 0.0);
+        let (origin, direction, right) = match self.projection {
+            Projection::Perspective => {
+                let spread = (self.fov.to_radians() / 2.0).tan();
+                (
+                    cgmath::Vector3::zero(),
+                    cgmath::vec3(x * spread, y * spread, 1.0),
+                    right,
+                )
+            }
+            Projection::Orthographic { width } => (
+                cgmath::vec3(x, y, 0.0) * width / 2.0,
+                cgmath::vec3(0.0, 0.0, 1.0),
+                right,
+            ),
+            Projection::Fisheye => {
+                let radius = x.hypot(y);
+                if radius > 1.0 {
+                    return None;
+                }
+                let (sin_theta, cos_theta) = (radius * self.fov.to_radians() / 2.0).sin_cos();
+                let (sin_phi, cos_phi) = y.atan2(x).sin_cos();
+                let direction = cgmath::vec3(sin_theta * cos_phi, sin_theta * sin_phi, cos_theta);
+                (cgmath::Vector3::zero(), direction, right)
+            }
+            Projection::Equirectangular => {
+                let longitude = x * std::f64::consts::PI;
+                let latitude = y * std::f64::consts::PI;
+                if latitude.abs() > std::f64::consts::FRAC_PI_2 {
+                    return None;
+                }
+                let direction = cgmath::vec3(
+                    latitude.cos() * longitude.sin(),
+                    latitude.sin(),
+                    latitude.cos() * longitude.cos(),
+                );
+                // the eyes turn with the way the ray is looking, so every direction gets the right depth
+                let right = cgmath::vec3(longitude.cos(), 0.0, -longitude.sin());
+                (cgmath::Vector3::zero(), direction, right)
+            }
+        };
+        let mut origin = origin + right * eye * self.eye_separation;
+        let mut direction = direction;
+
+        // with an aperture the ray starts somewhere on the lens and goes through the spot on the focal plane
+        // the pixel would see through a pinhole, so only things at the focal distance stay sharp.
+        // the projections that bend rays don't have a focal plane, so they stay sharp
+        if self.aperture > 0.0
+            && matches!(
+                self.projection,
+                Projection::Perspective | Projection::Orthographic { .. }
+            )
+        {
+            let focus = origin + direction * self.focal_distance;
+            let lens = self.sample_aperture(rand) * self.aperture;
+            origin += cgmath::vec3(lens.x, lens.y, 0.0);
+            direction = focus - origin;
+        }
+
+        let rotation = self.rotation_matrix();
+        Some((
+            self.position + rotation * origin,
+            (rotation * direction).normalize(),
+        ))
+    }
+
+    // how wide a pixel is along the rays through the middle of a picture that's width pixels across
+    pub fn footprint(&self, width: u32) -> Footprint {
+        // each eye only gets half of the picture
+        let width = if self.stereo {
+            width as f64 / 2.0
+        } else {
+            width as f64
+        };
+        let spread = |angle: f64| Footprint {
+            base: 0.0,
+            spread: angle / width,
+        };
+        match self.projection {
+            Projection::Perspective => spread(2.0 * (self.fov.to_radians() / 2.0).tan()),
+            Projection::Orthographic { width: across } => Footprint {
+                base: across / width,
+                spread: 0.0,
+            },
+            Projection::Fisheye => spread(self.fov.to_radians()),
+            Projection::Equirectangular => spread(2.0 * std::f64::consts::PI),
+        }
+    }
+
+    // a uniformly picked point on the aperture, which has a radius of 1
+    fn sample_aperture<T: rand::Rng>(&self, rand: &mut T) -> cgmath::Vector2<f64> {
+        let (u1, u2): (f64, f64) = (rand.gen(), rand.gen());
+        if self.blades < 3 {
+            let (sin, cos) = (2.0 * std::f64::consts::PI * u2).sin_cos();
+            return cgmath::vec2(cos, sin) * u1.sqrt();
+        }
+        // the polygon is a fan of equal triangles around the middle, pick one and then a point in it
+        let blade = rand.gen_range(0..self.blades);
+        let corner = |i: u32| {
+            let angle = self.blade_rotation.to_radians()
+                + 2.0 * std::f64::consts::PI * i as f64 / self.blades as f64;
+            cgmath::vec2(angle.cos(), angle.sin())
+        };
+        let (a, b) = (corner(blade), corner(blade + 1));
+        let (u1, u2) = if u1 + u2 > 1.0 {
+            (1.0 - u1, 1.0 - u2)
+        } else {
+            (u1, u2)
+        };
+        a * u1 + b * u2
+    }
+}
@@ -0,0 +1,129 @@
+// ways of looking at what cast_ray is doing instead of rendering the scene properly
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DebugMode {
+    // how many steps the march took, from blue for few to red for the most it's allowed
+    Steps,
+    // white where the ray hit something and black where it didn't
+    HitMask,
+    Normals,
+    // the distance field on a plane through the scene, see Slice
+    Slice,
+    // the scene in grey with the rays that ran out of steps before hitting or missing anything in magenta
+    StepLimit,
+}
+
+impl DebugMode {
+    pub const ALL: [DebugMode; 5] = [
+        DebugMode::Steps,
+        DebugMode::HitMask,
+        DebugMode::Normals,
+        DebugMode::Slice,
+        DebugMode::StepLimit,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|mode| mode.name() == name)
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DebugMode::Steps => "steps",
+            DebugMode::HitMask => "hit_mask",
+            DebugMode::Normals => "normals",
+            DebugMode::Slice => "slice",
+            DebugMode::StepLimit => "step_limit",
+        }
+    }
+
+    // for cycling through them in the viewer, with the normal render before the first and after the last
+    pub fn next(mode: Option<Self>) -> Option<Self> {
+        match mode {
+            None => Some(Self::ALL[0]),
+            Some(mode) => {
+                let index = Self::ALL.iter().position(|&m| m == mode).unwrap();
+                Self::ALL.get(index + 1).copied()
+            }
+        }
+    }
+}
+
+// a plane going across one of the axes, the slice mode draws the distance field on it
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Slice {
+    // 0, 1 or 2 for x, y or z
+    pub axis: usize,
+    pub offset: f64,
+}
+
+impl Default for Slice {
+    fn default() -> Self {
+        Self {
+            axis: 1,
+            offset: 0.0,
+        }
+    }
+}
+
+impl Slice {
+    // written like y=0.5
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let error = || format!("{} isn't a slice like y=0.5", text);
+        let (axis, offset) = text.split_once('=').ok_or_else(error)?;
+        let axis = match axis.trim() {
+            "x" => 0,
+            "y" => 1,
+            "z" => 2,
+            _ => return Err(error()),
+        };
+        let offset = offset.trim().parse::<f64>().map_err(|_| error())?;
+        Ok(Self { axis, offset })
+    }
+
+    pub fn next_axis(self) -> Self {
+        Self {
+            axis: (self.axis + 1) % 3,
+            ..self
+        }
+    }
+
+    // how far along the ray it crosses the plane, if it does
+    pub fn intersect(
+        &self,
+        from: cgmath::Point3<f64>,
+        direction: cgmath::Vector3<f64>,
+    ) -> Option<f64> {
+        // rays running along the plane never cross it, and would give infinity or nan here
+        let t = (self.offset - from[self.axis]) / direction[self.axis];
+        (t > 0.0 && t.is_finite()).then_some(t)
+    }
+}
+
+impl std::fmt::Display for Slice {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", ["x", "y", "z"][self.axis], self.offset)
+    }
+}
+
+// blue through green to red as value goes from 0 to 1
+pub fn heatmap(value: f64) -> cgmath::Vector3<f64> {
+    let value = value.clamp(0.0, 1.0);
+    cgmath::vec3(
+        (value * 2.0 - 1.0).clamp(0.0, 1.0),
+        1.0 - (value * 2.0 - 1.0).abs(),
+        (1.0 - value * 2.0).clamp(0.0, 1.0),
+    )
+}
+
+// orange outside and blue inside, with a band for every 0.1 of distance and the surface itself in white
+pub fn distance_color(distance: f64) -> cgmath::Vector3<f64> {
+    let base = if distance > 0.0 {
+        cgmath::vec3(0.9, 0.6, 0.3)
+    } else {
+        cgmath::vec3(0.4, 0.7, 0.85)
+    };
+    let fade = 1.0 - (-4.0 * distance.abs()).exp();
+    let bands = 0.8 + 0.2 * (distance * 20.0 * std::f64::consts::PI).cos();
+    let color = base * (fade * bands) + base * 0.2;
+    let surface = (1.0 - distance.abs() / 0.01).clamp(0.0, 1.0);
+    color * (1.0 - surface) + cgmath::vec3(1.0, 1.0, 1.0) * surface
+}
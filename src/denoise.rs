@@ -0,0 +1,116 @@
+use cgmath::InnerSpace;
+use rayon::prelude::*;
+
+use crate::aov::Aovs;
+
+// each pass spreads the filter out twice as far as the one before
+const PASSES: u32 = 5;
+// b3 spline, the same one used for every pass with holes between the taps
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+// how different two pixels can be before they stop getting blended together.
+// for the color that's in standard deviations of the pixels around it, so noisier parts get blurred more
+const COLOR_SIGMA: f64 = 4.0;
+const NORMAL_SIGMA: f64 = 0.3;
+const ALBEDO_SIGMA: f64 = 0.1;
+
+// edge avoiding a-trous wavelet filtering. the noise lives in the lighting and not in the textures,
+// so the color gets divided by the albedo first and multiplied back in at the end. pixels only get blended
+// with neighbours that have about the same normal and albedo, so edges and texture detail stay sharp
+pub fn denoise(
+    color: &[cgmath::Vector3<f64>],
+    features: &[Aovs],
+    (width, height): (u32, u32),
+) -> Vec<cgmath::Vector3<f64>> {
+    let albedo: Vec<_> = features
+        .iter()
+        .map(|f| f.albedo.map(|c| c.max(0.01)))
+        .collect();
+    let mut lighting: Vec<_> = color
+        .iter()
+        .zip(&albedo)
+        .map(|(c, a)| cgmath::vec3(c.x / a.x, c.y / a.y, c.z / a.z))
+        .collect();
+
+    for pass in 0..PASSES {
+        lighting = filter_pass(&lighting, features, (width, height), 1 << pass);
+    }
+
+    lighting
+        .iter()
+        .zip(&albedo)
+        .map(|(l, a)| cgmath::vec3(l.x * a.x, l.y * a.y, l.z * a.z))
+        .collect()
+}
+
+fn filter_pass(
+    lighting: &[cgmath::Vector3<f64>],
+    features: &[Aovs],
+    (width, height): (u32, u32),
+    step: i64,
+) -> Vec<cgmath::Vector3<f64>> {
+    let luminance: Vec<_> = lighting.iter().map(|c| luminance(*c)).collect();
+    let deviation = local_deviation(&luminance, (width, height));
+    (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = ((index % width) as i64, (index / width) as i64);
+            let center = &features[index as usize];
+            let center_luminance = luminance[index as usize];
+            let color_sigma = COLOR_SIGMA * deviation[index as usize] + 1e-6;
+
+            let mut sum = cgmath::vec3(0.0, 0.0, 0.0);
+            let mut total_weight = 0.0;
+            for (dy, ky) in (-2..=2).zip(KERNEL) {
+                for (dx, kx) in (-2..=2).zip(KERNEL) {
+                    let (sx, sy) = (x + dx * step, y + dy * step);
+                    if sx < 0 || sy < 0 || sx >= width as i64 || sy >= height as i64 {
+                        continue;
+                    }
+                    let other = (sy * width as i64 + sx) as usize;
+                    let feature = &features[other];
+                    let distance2 = |a: cgmath::Vector3<f64>, b: cgmath::Vector3<f64>| {
+                        let d = a - b;
+                        d.x * d.x + d.y * d.y + d.z * d.z
+                    };
+                    let weight = kx
+                        * ky
+                        * (-(center_luminance - luminance[other]).abs() / color_sigma
+                            - distance2(center.normal, feature.normal)
+                                / (NORMAL_SIGMA * NORMAL_SIGMA)
+                            - distance2(center.albedo, feature.albedo)
+                                / (ALBEDO_SIGMA * ALBEDO_SIGMA))
+                            .exp();
+                    sum += lighting[other] * weight;
+                    total_weight += weight;
+                }
+            }
+            // the center always counts, so this never divides by 0
+            sum / total_weight
+        })
+        .collect()
+}
+
+fn luminance(color: cgmath::Vector3<f64>) -> f64 {
+    color.dot(cgmath::vec3(0.2126, 0.7152, 0.0722))
+}
+
+// the standard deviation of the luminance in the 3x3 pixels around each one, a stand in for how noisy it is
+fn local_deviation(luminance: &[f64], (width, height): (u32, u32)) -> Vec<f64> {
+    (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let (x, y) = ((index % width) as i64, (index / width) as i64);
+            let (mut sum, mut sum2, mut count) = (0.0, 0.0, 0.0);
+            for sy in (y - 1).max(0)..=(y + 1).min(height as i64 - 1) {
+                for sx in (x - 1).max(0)..=(x + 1).min(width as i64 - 1) {
+                    let value = luminance[(sy * width as i64 + sx) as usize];
+                    sum += value;
+                    sum2 += value * value;
+                    count += 1.0;
+                }
+            }
+            let mean = sum / count;
+            (sum2 / count - mean * mean).max(0.0).sqrt()
+        })
+        .collect()
+}
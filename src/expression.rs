@@ -0,0 +1,298 @@
+// the little arithmetic language scene files can use in place of numbers and vectors, like "radius * 2" or
+// "offset + (0, height, 1)". it has + - * / ^, parentheses, .x .y .z on vectors and a few functions
+#[derive(Clone, Copy, Debug)]
+pub enum Value {
+    Scalar(f64),
+    Vector(cgmath::Vector3<f64>),
+}
+
+impl Value {
+    pub fn scalar(self) -> Result<f64, String> {
+        match self {
+            Self::Scalar(v) => Ok(v),
+            Self::Vector(_) => Err("expected a number but got a vector".to_string()),
+        }
+    }
+
+    pub fn vector(self) -> Result<cgmath::Vector3<f64>, String> {
+        match self {
+            Self::Vector(v) => Ok(v),
+            Self::Scalar(_) => Err("expected a vector but got a number".to_string()),
+        }
+    }
+}
+
+// variables are looked up through lookup, so they can be worked out on demand
+pub fn evaluate(
+    source: &str,
+    lookup: &mut dyn FnMut(&str) -> Result<Value, String>,
+) -> Result<Value, String> {
+    let mut parser = Parser {
+        chars: source.chars().collect(),
+        position: 0,
+        lookup,
+    };
+    let value = parser
+        .expression()
+        .map_err(|e| format!("in \"{}\": {}", source, e))?;
+    parser.skip_whitespace();
+    if parser.position < parser.chars.len() {
+        return Err(format!(
+            "in \"{}\": unexpected '{}'",
+            source, parser.chars[parser.position]
+        ));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    chars: Vec<char>,
+    position: usize,
+    lookup: &'a mut dyn FnMut(&str) -> Result<Value, String>,
+}
+
+fn add(a: Value, b: Value, subtract: bool) -> Result<Value, String> {
+    let sign = if subtract { -1.0 } else { 1.0 };
+    match (a, b) {
+        (Value::Scalar(a), Value::Scalar(b)) => Ok(Value::Scalar(a + sign * b)),
+        (Value::Vector(a), Value::Vector(b)) => Ok(Value::Vector(a + b * sign)),
+        _ => Err("can't add or subtract a number and a vector".to_string()),
+    }
+}
+
+fn multiply(a: Value, b: Value, divide: bool) -> Result<Value, String> {
+    match (a, b, divide) {
+        (Value::Scalar(a), Value::Scalar(b), false) => Ok(Value::Scalar(a * b)),
+        (Value::Scalar(a), Value::Scalar(b), true) => Ok(Value::Scalar(a / b)),
+        (Value::Vector(a), Value::Scalar(b), false)
+        | (Value::Scalar(b), Value::Vector(a), false) => Ok(Value::Vector(a * b)),
+        (Value::Vector(a), Value::Scalar(b), true) => Ok(Value::Vector(a / b)),
+        _ => Err("vectors can only be multiplied or divided by numbers".to_string()),
+    }
+}
+
+impl Parser<'_> {
+    fn skip_whitespace(&mut self) {
+        while self.position < self.chars.len() && self.chars[self.position].is_whitespace() {
+            self.position += 1;
+        }
+    }
+
+    // skips whitespace and takes c if it comes next
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.chars.get(self.position) == Some(&c) {
+            self.position += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.eat(c) {
+            Ok(())
+        } else {
+            Err(format!("expected '{}'", c))
+        }
+    }
+
+    fn expression(&mut self) -> Result<Value, String> {
+        let mut value = self.term()?;
+        loop {
+            if self.eat('+') {
+                value = add(value, self.term()?, false)?;
+            } else if self.eat('-') {
+                value = add(value, self.term()?, true)?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn term(&mut self) -> Result<Value, String> {
+        let mut value = self.unary()?;
+        loop {
+            if self.eat('*') {
+                value = multiply(value, self.unary()?, false)?;
+            } else if self.eat('/') {
+                value = multiply(value, self.unary()?, true)?;
+            } else {
+                return Ok(value);
+            }
+        }
+    }
+
+    fn unary(&mut self) -> Result<Value, String> {
+        if self.eat('-') {
+            return multiply(self.unary()?, Value::Scalar(-1.0), false);
+        }
+        let base = self.postfix()?;
+        if self.eat('^') {
+            let exponent = self.unary()?.scalar()?;
+            return Ok(Value::Scalar(base.scalar()?.powf(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn postfix(&mut self) -> Result<Value, String> {
+        let mut value = self.primary()?;
+        while self.eat('.') {
+            let vector = value.vector()?;
+            value = Value::Scalar(match self.identifier().as_str() {
+                "x" => vector.x,
+                "y" => vector.y,
+                "z" => vector.z,
+                other => return Err(format!("vectors have no .{}", other)),
+            });
+        }
+        Ok(value)
+    }
+
+    fn identifier(&mut self) -> String {
+        self.skip_whitespace();
+        let start = self.position;
+        while self.position < self.chars.len()
+            && (self.chars[self.position].is_alphanumeric() || self.chars[self.position] == '_')
+        {
+            self.position += 1;
+        }
+        self.chars[start..self.position].iter().collect()
+    }
+
+    fn primary(&mut self) -> Result<Value, String> {
+        if self.eat('(') {
+            let first = self.expression()?;
+            if self.eat(',') {
+                let y = self.expression()?.scalar()?;
+                self.expect(',')?;
+                let z = self.expression()?.scalar()?;
+                self.expect(')')?;
+                return Ok(Value::Vector(cgmath::vec3(first.scalar()?, y, z)));
+            }
+            self.expect(')')?;
+            return Ok(first);
+        }
+
+        self.skip_whitespace();
+        match self.chars.get(self.position) {
+            Some(c) if c.is_ascii_digit() || *c == '.' => return self.number(),
+            Some(c) if c.is_alphabetic() || *c == '_' => {}
+            Some(c) => return Err(format!("unexpected '{}'", c)),
+            None => return Err("unexpected end".to_string()),
+        }
+
+        let name = self.identifier();
+        if self.eat('(') {
+            let mut arguments = vec![self.expression()?];
+            while self.eat(',') {
+                arguments.push(self.expression()?);
+            }
+            self.expect(')')?;
+            return call(&name, &arguments);
+        }
+        match name.as_str() {
+            "pi" => Ok(Value::Scalar(std::f64::consts::PI)),
+            _ => (self.lookup)(&name),
+        }
+    }
+
+    fn number(&mut self) -> Result<Value, String> {
+        let start = self.position;
+        while self.position < self.chars.len()
+            && (self.chars[self.position].is_ascii_digit() || self.chars[self.position] == '.')
+        {
+            self.position += 1;
+        }
+        // exponents like 1e-3
+        if self.position < self.chars.len() && matches!(self.chars[self.position], 'e' | 'E') {
+            self.position += 1;
+            if self.position < self.chars.len() && matches!(self.chars[self.position], '+' | '-') {
+                self.position += 1;
+            }
+            while self.position < self.chars.len() && self.chars[self.position].is_ascii_digit() {
+                self.position += 1;
+            }
+        }
+        let text: String = self.chars[start..self.position].iter().collect();
+        text.parse()
+            .map(Value::Scalar)
+            .map_err(|_| format!("{} isn't a number", text))
+    }
+}
+
+fn call(name: &str, arguments: &[Value]) -> Result<Value, String> {
+    let scalar = |i: usize| -> Result<f64, String> {
+        arguments
+            .get(i)
+            .ok_or_else(|| format!("{} needs more arguments", name))?
+            .scalar()
+    };
+    let vector = |i: usize| -> Result<cgmath::Vector3<f64>, String> {
+        arguments
+            .get(i)
+            .ok_or_else(|| format!("{} needs more arguments", name))?
+            .vector()
+    };
+    use cgmath::InnerSpace;
+    Ok(match name {
+        "sin" => Value::Scalar(scalar(0)?.sin()),
+        "cos" => Value::Scalar(scalar(0)?.cos()),
+        "tan" => Value::Scalar(scalar(0)?.tan()),
+        "sqrt" => Value::Scalar(scalar(0)?.sqrt()),
+        "abs" => Value::Scalar(scalar(0)?.abs()),
+        "floor" => Value::Scalar(scalar(0)?.floor()),
+        "min" => Value::Scalar(scalar(0)?.min(scalar(1)?)),
+        "max" => Value::Scalar(scalar(0)?.max(scalar(1)?)),
+        "pow" => Value::Scalar(scalar(0)?.powf(scalar(1)?)),
+        "radians" => Value::Scalar(scalar(0)?.to_radians()),
+        "length" => Value::Scalar(vector(0)?.magnitude()),
+        "normalize" => Value::Vector(vector(0)?.normalize()),
+        "dot" => Value::Scalar(vector(0)?.dot(vector(1)?)),
+        "cross" => Value::Vector(vector(0)?.cross(vector(1)?)),
+        _ => return Err(format!("unknown function {}", name)),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scalar(source: &str) -> f64 {
+        evaluate(source, &mut |name| Err(format!("unknown param {}", name)))
+            .unwrap()
+            .scalar()
+            .unwrap()
+    }
+
+    fn error(source: &str) -> String {
+        match evaluate(source, &mut |name| Err(format!("unknown param {}", name))) {
+            Ok(value) => panic!("\"{}\" gave {:?} instead of an error", source, value),
+            Err(e) => e,
+        }
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(scalar("1 + 2 * 3"), 7.0);
+        assert_eq!(scalar("(1 + 2) * 3"), 9.0);
+        assert_eq!(scalar("8 - 2 - 1"), 5.0);
+        assert_eq!(scalar("8 / 2 / 2"), 2.0);
+        // powers bind tighter than the minus in front and go right to left
+        assert_eq!(scalar("-2 ^ 2"), -4.0);
+        assert_eq!(scalar("2 ^ 3 ^ 2"), 512.0);
+        assert_eq!(scalar("2 * (1, 2, 3).y + 1e1"), 14.0);
+        assert_eq!(scalar("max(1, 2) * length((3, 0, 4))"), 10.0);
+    }
+
+    #[test]
+    fn errors() {
+        assert!(error("1 +").contains("unexpected end"));
+        assert!(error("(1 + 2").contains("expected ')'"));
+        assert!(error("1 2").contains("unexpected '2'"));
+        assert!(error("1 + (1, 2, 3)").contains("can't add"));
+        assert!(error("(1, 2, 3).w").contains("no .w"));
+        assert!(error("nope(1)").contains("unknown function nope"));
+        assert!(error("radius * 2").contains("unknown param radius"));
+    }
+}
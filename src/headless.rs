@@ -0,0 +1,190 @@
+use std::path::Path;
+
+use rayon::prelude::*;
+
+use crate::{
+    aov::{self, Aov},
+    debug::{DebugMode, Slice},
+    denoise::denoise,
+    pixel_drawer::{render_pixel, render_pixel_debug, World},
+    tone_mapping::ToneMapping,
+};
+
+// how a sequence gets rendered and where it ends up
+pub struct Settings {
+    pub size: (u32, u32),
+    pub samples: u32,
+    // a file name with #s where the frame number goes
+    pub output: String,
+    pub tone_mapping: ToneMapping,
+    pub aovs: Vec<Aov>,
+    pub denoise: bool,
+    // renders what the debug mode shows instead of the scene, without tone mapping
+    pub debug: Option<DebugMode>,
+    pub slice: Slice,
+}
+
+// averages samples of pixel for every pixel of the image
+fn average_samples(
+    (width, height): (u32, u32),
+    samples: u32,
+    pixel: impl Fn(u32) -> cgmath::Vector3<f64> + Sync,
+) -> Vec<cgmath::Vector3<f64>> {
+    (0..width * height)
+        .into_par_iter()
+        .map(|index| {
+            let sum = (0..samples)
+                .map(|_| pixel(index))
+                .fold(cgmath::vec3(0.0, 0.0, 0.0), |a, b| a + b);
+            sum / samples.max(1) as f64
+        })
+        .collect()
+}
+
+// renders the world as it is set up right now, averaging samples rays for every pixel
+pub fn render_image(world: &World, size: (u32, u32), samples: u32) -> Vec<cgmath::Vector3<f64>> {
+    average_samples(size, samples, |index| {
+        let (r, g, b, _) = render_pixel(size, index, world);
+        cgmath::vec3(r, g, b)
+    })
+}
+
+pub fn render_debug(
+    world: &World,
+    size: (u32, u32),
+    samples: u32,
+    mode: DebugMode,
+    slice: Slice,
+) -> Vec<cgmath::Vector3<f64>> {
+    average_samples(size, samples, |index| {
+        render_pixel_debug(size, index, world, mode, slice)
+    })
+}
+
+fn create_parent(path: &Path) -> Result<(), String> {
+    match path.parent() {
+        Some(parent) => std::fs::create_dir_all(parent)
+            .map_err(|e| format!("failed to create {}: {}", parent.display(), e)),
+        None => Ok(()),
+    }
+}
+
+// pngs get tone mapped, hdrs keep the raw values
+pub fn save_image(
+    path: &Path,
+    pixels: &[cgmath::Vector3<f64>],
+    (width, height): (u32, u32),
+    tone_mapping: &ToneMapping,
+) -> Result<(), String> {
+    create_parent(path)?;
+    let is_hdr = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("hdr"));
+    let result = if is_hdr {
+        let pixels: Vec<_> = pixels
+            .iter()
+            .map(|p| image::Rgb([p.x as f32, p.y as f32, p.z as f32]))
+            .collect();
+        std::fs::File::create(path)
+            .map_err(|e| e.to_string())
+            .and_then(|file| {
+                image::codecs::hdr::HdrEncoder::new(std::io::BufWriter::new(file))
+                    .encode(&pixels, width as usize, height as usize)
+                    .map_err(|e| e.to_string())
+            })
+    } else {
+        let bytes: Vec<u8> = pixels
+            .iter()
+            .flat_map(|p| {
+                let mapped = tone_mapping.map(*p);
+                [mapped.x, mapped.y, mapped.z].map(|c| (c * 255.0).round() as u8)
+            })
+            .collect();
+        image::save_buffer(path, &bytes, width, height, image::ColorType::Rgb8)
+            .map_err(|e| e.to_string())
+    };
+    result.map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+// for pictures that are already between 0 and 1, so they go out as they are
+fn save_visualization(
+    path: &Path,
+    pixels: &[cgmath::Vector3<f64>],
+    (width, height): (u32, u32),
+) -> Result<(), String> {
+    create_parent(path)?;
+    let bytes: Vec<u8> = pixels
+        .iter()
+        .flat_map(|p| [p.x, p.y, p.z].map(|c| (c.clamp(0.0, 1.0) * 255.0).round() as u8))
+        .collect();
+    image::save_buffer(path, &bytes, width, height, image::ColorType::Rgb8)
+        .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+// the run of #s in the pattern gets replaced by the frame number, padded out to as many digits
+pub fn frame_path(pattern: &str, frame: u32) -> Result<String, String> {
+    let start = pattern
+        .find('#')
+        .ok_or_else(|| format!("{} needs #s where the frame number goes", pattern))?;
+    let digits = pattern[start..].chars().take_while(|&c| c == '#').count();
+    Ok(format!(
+        "{}{:0width$}{}",
+        &pattern[..start],
+        frame,
+        &pattern[start + digits..],
+        width = digits
+    ))
+}
+
+// frame_0001.png gets its aovs next to it as frame_0001.depth.png and so on
+fn aov_path(path: &Path, aov: Aov) -> std::path::PathBuf {
+    let stem = path.file_stem().and_then(|s| s.to_str()).unwrap_or("");
+    path.with_file_name(format!("{}.{}.png", stem, aov.name()))
+}
+
+pub fn render_sequence(
+    world: &mut World,
+    frames: std::ops::Range<u32>,
+    settings: &Settings,
+) -> Result<(), String> {
+    for frame in frames {
+        world.set_time(world.animation.time_of_frame(frame));
+        let path = frame_path(&settings.output, frame)?;
+        let path = Path::new(&path);
+        if let Some(mode) = settings.debug {
+            let pixels = render_debug(world, settings.size, settings.samples, mode, settings.slice);
+            save_visualization(path, &pixels, settings.size)?;
+            println!("rendered frame {} to {}", frame, path.display());
+            continue;
+        }
+
+        let mut pixels = render_image(world, settings.size, settings.samples);
+        let is_exr = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("exr"));
+
+        // the denoiser needs the albedo and normals even when they aren't being saved
+        let aovs = if settings.aovs.is_empty() && !settings.denoise {
+            Vec::new()
+        } else {
+            aov::render(world, settings.size, settings.samples)
+        };
+        if settings.denoise {
+            pixels = denoise(&pixels, &aovs, settings.size);
+        }
+        if is_exr {
+            create_parent(path)?;
+            aov::save_exr(path, &pixels, &aovs, &settings.aovs, settings.size)?;
+        } else {
+            save_image(path, &pixels, settings.size, &settings.tone_mapping)?;
+            for &aov in &settings.aovs {
+                let image = aov::visualize(aov, &aovs, &settings.tone_mapping);
+                save_visualization(&aov_path(path, aov), &image, settings.size)?;
+            }
+        }
+        println!("rendered frame {} to {}", frame, path.display());
+    }
+    Ok(())
+}
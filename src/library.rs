@@ -0,0 +1,79 @@
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    material::{MaterialDefinition, MaterialRef},
+    migration::Upgrade,
+    pixel_drawer::Object,
+    scene_file,
+};
+
+// a file that gets pulled into a scene with include, like a collection of materials or props.
+// its objects are added to the scene and its materials and definitions can be used as if the scene had them
+#[derive(Serialize, Deserialize, Default)]
+#[serde(default)]
+pub struct Library {
+    pub include: Vec<String>,
+    pub materials: HashMap<String, MaterialDefinition>,
+    pub definitions: HashMap<String, Object>,
+    pub objects: Vec<Object>,
+}
+
+impl Library {
+    // other wins when both have something with the same name
+    fn merge(&mut self, other: Library) {
+        self.materials.extend(other.materials);
+        self.definitions.extend(other.definitions);
+        self.objects.extend(other.objects);
+    }
+
+    fn load_images(&mut self, base: &Path) -> Result<(), String> {
+        for material in self.materials.values_mut() {
+            material.textures.load_images(base)?;
+        }
+        for object in self.definitions.values_mut().chain(&mut self.objects) {
+            object.load_images(base)?;
+            // inline materials only get resolved once they're in the scene, which is too late to know where they came from
+            for material in object.material_refs_mut() {
+                if let MaterialRef::Inline(definition) = material {
+                    definition.textures.load_images(base)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Upgrade for Library {}
+
+// loads everything in include and what those include in turn, paths are relative to base.
+// files are only ever loaded once, so including something twice or in a circle is fine
+pub fn load_includes(
+    include: &[String],
+    base: &Path,
+    visited: &mut HashSet<PathBuf>,
+) -> Result<Library, String> {
+    let mut merged = Library::default();
+    for path in include {
+        let path = base.join(path);
+        let canonical = path
+            .canonicalize()
+            .map_err(|e| format!("can't find included file {}: {}", path.display(), e))?;
+        if !visited.insert(canonical) {
+            continue;
+        }
+
+        let mut library: Library = scene_file::load(&path)?;
+        let library_base = path.parent().unwrap_or_else(|| Path::new("."));
+        library.load_images(library_base)?;
+
+        let mut nested = load_includes(&library.include, library_base, visited)?;
+        nested.merge(library);
+        merged.merge(nested);
+    }
+    Ok(merged)
+}
@@ -0,0 +1,95 @@
+use cgmath::InnerSpace;
+use rand::{Rng, SeedableRng};
+use rand_distr::Distribution;
+
+// objects that go on forever get points picked this far from the origin, in every direction
+pub const EXTENT: f64 = 10.0;
+pub const SAMPLES: u32 = 20000;
+// worked out factors get some room on top of the worst that was found, since the sampling can miss the worst spot
+const MARGIN: f64 = 1.1;
+// how far over 1 the ratio can go before it counts, so rounding errors don't
+const TOLERANCE: f64 = 1e-3;
+
+// how well a distance estimate keeps to being at most the real distance
+pub struct Report {
+    pub samples: u32,
+    // how many samples found the estimate changing faster than distance does
+    pub violations: u32,
+    // the fastest it changed per unit of distance, over 1 means rays can step past surfaces
+    pub worst: f64,
+    pub worst_point: cgmath::Point3<f64>,
+    pub worst_time: f64,
+}
+
+impl Report {
+    // what the estimate needs dividing by to be safe
+    pub fn factor(&self) -> f64 {
+        (self.worst * MARGIN).max(1.0)
+    }
+}
+
+// every sample point gets compared with another one inside the sphere its estimate says is empty,
+// which is where a ray starting there would step to. a real distance can't change by more than the points are apart.
+// points get picked up to extent away from center along each axis. it's seeded so the same scene always gets the same answer
+pub fn check(
+    distance: impl Fn(cgmath::Point3<f64>, f64) -> f64,
+    times: std::ops::Range<f64>,
+    samples: u32,
+    (center, extent): (cgmath::Point3<f64>, f64),
+) -> Report {
+    let mut rand = rand::rngs::StdRng::seed_from_u64(0);
+    let mut report = Report {
+        samples,
+        violations: 0,
+        worst: 0.0,
+        worst_point: cgmath::point3(0.0, 0.0, 0.0),
+        worst_time: times.start,
+    };
+    for _ in 0..samples {
+        let mut point = center
+            + cgmath::vec3(
+                rand.gen_range(-extent..extent),
+                rand.gen_range(-extent..extent),
+                rand.gen_range(-extent..extent),
+            );
+        let time = if times.is_empty() {
+            times.start
+        } else {
+            rand.gen_range(times.clone())
+        };
+        // most of the space is far from everything, and blends and cuts go wrong close to surfaces,
+        // so half of the points get pulled towards the surface nearest to them first
+        if rand.gen::<bool>() {
+            let e = 1e-4;
+            let gradient = cgmath::vec3(
+                distance(point + cgmath::vec3(e, 0.0, 0.0), time)
+                    - distance(point - cgmath::vec3(e, 0.0, 0.0), time),
+                distance(point + cgmath::vec3(0.0, e, 0.0), time)
+                    - distance(point - cgmath::vec3(0.0, e, 0.0), time),
+                distance(point + cgmath::vec3(0.0, 0.0, e), time)
+                    - distance(point - cgmath::vec3(0.0, 0.0, e), time),
+            ) / (2.0 * e);
+            let pulled = point - gradient.normalize() * distance(point, time) * rand.gen::<f64>();
+            if pulled.x.is_finite() && pulled.y.is_finite() && pulled.z.is_finite() {
+                point = pulled;
+            }
+        }
+        let estimate = distance(point, time);
+        let [x, y, z] = rand_distr::UnitSphere.sample(&mut rand);
+        let apart = (estimate.abs() * rand.gen::<f64>()).max(1e-4);
+        let other = point + cgmath::vec3(x, y, z) * apart;
+        let ratio = (estimate - distance(other, time)).abs() / apart;
+        if !ratio.is_finite() {
+            continue;
+        }
+        if ratio > 1.0 + TOLERANCE {
+            report.violations += 1;
+        }
+        if ratio > report.worst {
+            report.worst = ratio;
+            report.worst_point = point;
+            report.worst_time = time;
+        }
+    }
+    report
+}
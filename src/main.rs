@@ -1,5 +1,24 @@
+mod animation;
+mod aov;
+mod atmosphere;
+mod bsdf;
+mod camera;
+mod debug;
+mod denoise;
 mod error_extra;
+mod expression;
+mod headless;
+mod library;
+mod lipschitz;
+mod march;
+mod material;
+mod migration;
+mod params;
 mod pixel_drawer;
+mod scene_deserializer;
+mod scene_file;
+mod texture;
+mod tone_mapping;
 
 use std::{
     ops::Add,
@@ -19,7 +38,181 @@ fn main() {
 fn runner() -> color_eyre::Result<()> {
     env_logger::init();
 
-    let world_filename = std::env::args().nth(1);
+    // `convert from.ron to.json` rewrites a scene in another format instead of rendering it
+    if std::env::args().nth(1).as_deref() == Some("convert") {
+        let mut args = std::env::args().skip(2);
+        let from = args
+            .next()
+            .ok_or("convert needs a scene to read")
+            .wrap_error()?;
+        let to = args
+            .next()
+            .ok_or("convert needs a file to write to")
+            .wrap_error()?;
+        let world: pixel_drawer::World =
+            scene_file::load(std::path::Path::new(&from)).wrap_error()?;
+        scene_file::save(&world, std::path::Path::new(&to)).wrap_error()?;
+        println!("converted {} to {}", from, to);
+        return Ok(());
+    }
+
+    // `validate scene.ron` looks for places where the distance estimates of objects could let rays step through surfaces
+    if std::env::args().nth(1).as_deref() == Some("validate") {
+        let mut file = None;
+        let mut samples = lipschitz::SAMPLES;
+        let mut extent = lipschitz::EXTENT;
+        let mut args = std::env::args().skip(2);
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--samples" => {
+                    let value = args.next().ok_or("--samples needs a number").wrap_error()?;
+                    samples = value.parse::<u32>()?;
+                }
+                "--extent" => {
+                    let value = args
+                        .next()
+                        .ok_or("--extent needs a distance")
+                        .wrap_error()?;
+                    extent = value.parse::<f64>()?;
+                }
+                _ => file = Some(arg),
+            }
+        }
+        let file = file.ok_or("validate needs a scene to check").wrap_error()?;
+        let path = std::path::Path::new(&file);
+        let mut world: pixel_drawer::World = scene_file::load(path).wrap_error()?;
+        world.prepare(path).wrap_error()?;
+        for (index, (object, report)) in world
+            .objects
+            .iter()
+            .zip(world.check_bounds(samples, extent))
+            .enumerate()
+        {
+            print!(
+                "object {} ({}): the estimate changes up to {:.2} times as fast as the distance",
+                index,
+                object.kind(),
+                report.worst
+            );
+            if report.violations == 0 {
+                println!(", which is fine");
+                continue;
+            }
+            let p = report.worst_point;
+            print!(", the most at ({:.3}, {:.3}, {:.3})", p.x, p.y, p.z);
+            if object.is_animated() {
+                print!(" at {:.2}s", report.worst_time);
+            }
+            // the check already stepped as short as the factors in the object say, so the one it needs is on top of those
+            let factor = object.step_scale() * report.factor();
+            let fix = if let pixel_drawer::Object::Lipschitz { .. } = object {
+                format!("raising its factor to {:.2}", factor)
+            } else {
+                format!("wrapping it in Lipschitz with a factor of {:.2}", factor)
+            };
+            println!(
+                ". {} of {} samples break the bound, {} would fix that",
+                report.violations, report.samples, fix
+            );
+        }
+        return Ok(());
+    }
+
+    // `render scene.ron` renders frames to image files instead of opening a window
+    let headless = std::env::args().nth(1).as_deref() == Some("render");
+
+    let mut world_filename = None;
+    let mut tone_map_operator = None;
+    let mut exposure = None;
+    let mut frame = None;
+    let mut frames = None;
+    let mut size = (960, 960);
+    let mut samples = 64;
+    let mut output = "frames/frame_####.png".to_string();
+    let mut aovs = Vec::new();
+    let mut denoise = false;
+    let mut debug_mode = None;
+    let mut slice = debug::Slice::default();
+    let mut args = std::env::args().skip(if headless { 2 } else { 1 });
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--tone-map" => {
+                let name = args
+                    .next()
+                    .ok_or("--tone-map needs an operator")
+                    .wrap_error()?;
+                tone_map_operator = Some(
+                    tone_mapping::ToneMapOperator::from_name(&name)
+                        .ok_or(format!("unknown tone mapping operator {}", name))
+                        .wrap_error()?,
+                );
+            }
+            "--exposure" => {
+                let value = args.next().ok_or("--exposure needs a value").wrap_error()?;
+                exposure = Some(value.parse::<f64>()?);
+            }
+            "--frame" => {
+                let value = args.next().ok_or("--frame needs a number").wrap_error()?;
+                frame = Some(value.parse::<u32>()?);
+            }
+            "--frames" => {
+                let value = args
+                    .next()
+                    .ok_or("--frames needs a range like 0..48")
+                    .wrap_error()?;
+                let (start, end) = value
+                    .split_once("..")
+                    .ok_or("--frames needs a range like 0..48")
+                    .wrap_error()?;
+                frames = Some(start.parse::<u32>()?..end.parse::<u32>()?);
+            }
+            "--size" => {
+                let value = args
+                    .next()
+                    .ok_or("--size needs a size like 480x480")
+                    .wrap_error()?;
+                let (width, height) = value
+                    .split_once('x')
+                    .ok_or("--size needs a size like 480x480")
+                    .wrap_error()?;
+                size = (width.parse::<u32>()?, height.parse::<u32>()?);
+            }
+            "--samples" => {
+                let value = args.next().ok_or("--samples needs a number").wrap_error()?;
+                samples = value.parse::<u32>()?;
+            }
+            "--output" => {
+                output = args
+                    .next()
+                    .ok_or("--output needs a file name with #s for the frame number")
+                    .wrap_error()?;
+            }
+            "--aovs" => {
+                let value = args
+                    .next()
+                    .ok_or("--aovs needs a list like depth,normal or all")
+                    .wrap_error()?;
+                aovs = aov::parse_list(&value).wrap_error()?;
+            }
+            "--denoise" => denoise = true,
+            "--debug" => {
+                let name = args.next().ok_or("--debug needs a mode").wrap_error()?;
+                debug_mode = Some(
+                    debug::DebugMode::from_name(&name)
+                        .ok_or(format!("unknown debug mode {}", name))
+                        .wrap_error()?,
+                );
+            }
+            "--slice" => {
+                let value = args
+                    .next()
+                    .ok_or("--slice needs a plane like y=0.5")
+                    .wrap_error()?;
+                slice = debug::Slice::parse(&value).wrap_error()?;
+            }
+            _ => world_filename = Some(arg),
+        }
+    }
     let world_filename = match &world_filename {
         Some(s) => s.as_str(),
         None => {
@@ -28,10 +221,46 @@ fn runner() -> color_eyre::Result<()> {
         }
     };
 
-    let world = ron::de::from_reader(
-        std::fs::File::open(world_filename).expect("failed to open shapes file"),
-    )
-    .expect("failed to deserialize contents of shapes file");
+    let mut world: pixel_drawer::World =
+        scene_file::load(std::path::Path::new(world_filename)).wrap_error()?;
+    world
+        .prepare(std::path::Path::new(world_filename))
+        .wrap_error()?;
+
+    let mut initial_tone_mapping = world.tone_mapping;
+    if let Some(operator) = tone_map_operator {
+        initial_tone_mapping.operator = operator;
+    }
+    if let Some(exposure) = exposure {
+        initial_tone_mapping.exposure = exposure;
+    }
+
+    if headless {
+        let frames = frames
+            .or_else(|| frame.map(|f| f..f + 1))
+            .unwrap_or(world.animation.start..world.animation.end);
+        let settings = headless::Settings {
+            size,
+            samples,
+            output,
+            tone_mapping: initial_tone_mapping,
+            aovs,
+            denoise,
+            debug: debug_mode,
+            slice,
+        };
+        headless::render_sequence(&mut world, frames, &settings).wrap_error()?;
+        return Ok(());
+    }
+    if let Some(frame) = frame {
+        world.set_time(world.animation.time_of_frame(frame));
+    }
+    let display = Arc::new(Mutex::new(pixel_drawer::Display {
+        tone_mapping: initial_tone_mapping,
+        denoise,
+        debug: debug_mode,
+        slice,
+    }));
 
     let event_loop = winit::event_loop::EventLoop::new();
     let window = winit::window::WindowBuilder::new()
@@ -75,7 +304,10 @@ fn runner() -> color_eyre::Result<()> {
 
     let buffer_contents = Arc::new(Mutex::new(vec![0; (width * height * 4) as _]));
     let that_one = buffer_contents.clone();
-    std::thread::spawn(move || pixel_drawer::render_to_buffer(that_one, (width, height), &world));
+    let display_for_renderer = display.clone();
+    std::thread::spawn(move || {
+        pixel_drawer::render_to_buffer(that_one, display_for_renderer, (width, height), &world)
+    });
     event_loop.run(move |event, _, control| match event {
         winit::event::Event::WindowEvent {
             event: winit::event::WindowEvent::CloseRequested,
@@ -83,6 +315,63 @@ fn runner() -> color_eyre::Result<()> {
         } => {
             *control = winit::event_loop::ControlFlow::Exit;
         }
+        winit::event::Event::WindowEvent {
+            event:
+                winit::event::WindowEvent::KeyboardInput {
+                    input:
+                        winit::event::KeyboardInput {
+                            state: winit::event::ElementState::Pressed,
+                            virtual_keycode: Some(key),
+                            ..
+                        },
+                    ..
+                },
+            ..
+        } => {
+            // tone mapping and denoising only touch the displayed image, so this doesn't restart the render
+            let mut display = display.lock().unwrap();
+            let tone_mapping = &mut display.tone_mapping;
+            match key {
+                winit::event::VirtualKeyCode::T => {
+                    tone_mapping.operator = tone_mapping.operator.next();
+                }
+                winit::event::VirtualKeyCode::Up => tone_mapping.exposure += 0.5,
+                winit::event::VirtualKeyCode::Down => tone_mapping.exposure -= 0.5,
+                winit::event::VirtualKeyCode::D => {
+                    display.denoise = !display.denoise;
+                    println!("denoising: {}", if display.denoise { "on" } else { "off" });
+                    return;
+                }
+                // the debug modes and the slice plane start the picture over
+                winit::event::VirtualKeyCode::G => {
+                    display.debug = debug::DebugMode::next(display.debug);
+                    println!(
+                        "debug mode: {}",
+                        display.debug.map_or("off", |mode| mode.name())
+                    );
+                    return;
+                }
+                winit::event::VirtualKeyCode::X => {
+                    display.slice = display.slice.next_axis();
+                    println!("slice: {}", display.slice);
+                    return;
+                }
+                winit::event::VirtualKeyCode::PageUp | winit::event::VirtualKeyCode::PageDown => {
+                    display.slice.offset += if key == winit::event::VirtualKeyCode::PageUp {
+                        0.1
+                    } else {
+                        -0.1
+                    };
+                    println!("slice: {}", display.slice);
+                    return;
+                }
+                _ => return,
+            }
+            println!(
+                "tone mapping: {:?}, exposure: {:+} EV",
+                tone_mapping.operator, tone_mapping.exposure
+            );
+        }
         winit::event::Event::MainEventsCleared => {
             *control = winit::event_loop::ControlFlow::WaitUntil(
                 std::time::Instant::now().add(std::time::Duration::from_secs_f64(0.0166666)),
@@ -0,0 +1,77 @@
+use serde::{Deserialize, Serialize};
+
+// how rays get marched through the distance field
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct March {
+    // how long a ray keeps going before giving up
+    pub max_steps: u32,
+    // how close a ray has to get to a surface to count as hitting it
+    pub epsilon: f64,
+    // rays that get this far from everything have missed
+    pub far: f64,
+    // how far apart the samples for working out normals are
+    pub gradient_offset: f64,
+    // lets rays count as hitting once they're within this many pixels of a surface, so far away surfaces
+    // don't take hundreds of steps to reach and epsilon can be tiny for close ups. 0 turns it off
+    pub pixel_epsilon: f64,
+    // how much longer than the distance to the nearest surface each step is, between 1 and 2.
+    // something like 1.5 takes fewer steps, when a step might have gone past a surface the ray goes back
+    // and takes normal steps from there. 1 turns it off
+    pub relaxation: f64,
+    // how many times a hit gets narrowed down between the last two points the ray was at, to find
+    // where the surface is more exactly. 0 turns it off
+    pub refine_steps: u32,
+}
+
+impl Default for March {
+    fn default() -> Self {
+        Self {
+            max_steps: 1000,
+            epsilon: 0.0001,
+            far: 10000.0,
+            gradient_offset: 0.005,
+            pixel_epsilon: 0.0,
+            relaxation: 1.0,
+            refine_steps: 0,
+        }
+    }
+}
+
+impl March {
+    pub fn check(&self) -> Result<(), String> {
+        if self.epsilon <= 0.0 || self.gradient_offset <= 0.0 {
+            return Err("march epsilon and gradient_offset have to be above 0".to_string());
+        }
+        if self.far <= self.epsilon {
+            return Err("march far has to be further than epsilon".to_string());
+        }
+        if !(1.0..2.0).contains(&self.relaxation) {
+            return Err("march relaxation has to be at least 1 and below 2".to_string());
+        }
+        if self.pixel_epsilon < 0.0 {
+            return Err("march pixel_epsilon can't be negative".to_string());
+        }
+        Ok(())
+    }
+
+    // the hit epsilon for a ray that has come distance so far
+    pub fn epsilon_at(&self, footprint: Footprint, distance: f64) -> f64 {
+        self.epsilon
+            .max(self.pixel_epsilon * footprint.at(distance))
+    }
+}
+
+// how wide a pixel is along a ray, it starts out base wide and spreads out in a cone
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Footprint {
+    pub base: f64,
+    // per unit of distance
+    pub spread: f64,
+}
+
+impl Footprint {
+    pub fn at(&self, distance: f64) -> f64 {
+        self.base + self.spread * distance
+    }
+}
@@ -0,0 +1,252 @@
+use std::collections::HashMap;
+
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+use crate::{pixel_drawer::Material, texture::MaterialTextures};
+
+// how a material is written in a scene file. it has the same fields as Material but any of them can be left out,
+// those get taken from the base material if there is one and otherwise fall back to their defaults
+#[derive(Serialize, Deserialize, Clone, Default)]
+#[serde(default)]
+pub struct MaterialDefinition {
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub base: Option<String>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub color: Option<cgmath::Vector3<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub emitance: Option<cgmath::Vector3<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub metalness: Option<f64>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub roughness: Option<f64>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub ior: Option<f64>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub transmission: Option<f64>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub absorption: Option<cgmath::Vector3<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub scattering: Option<cgmath::Vector3<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub anisotropy: Option<f64>,
+    // each texture is inherited on its own, so a material can swap out just the bump map of its base
+    #[serde(skip_serializing_if = "MaterialTextures::is_empty")]
+    pub textures: MaterialTextures,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub bump_strength: Option<f64>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub specular_ior: Option<f64>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub specular_tint: Option<f64>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub specular_anisotropy: Option<f64>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub tangent: Option<cgmath::Vector3<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub clearcoat: Option<f64>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub clearcoat_roughness: Option<f64>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub sheen: Option<f64>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub sheen_color: Option<cgmath::Vector3<f64>>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub sheen_roughness: Option<f64>,
+}
+
+// lets the optional fields above be written as plain values instead of Some(..)
+pub mod present {
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    pub fn serialize<S: Serializer, T: Serialize>(
+        value: &Option<T>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match value {
+            Some(value) => value.serialize(serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>, T: Deserialize<'de>>(
+        deserializer: D,
+    ) -> Result<Option<T>, D::Error> {
+        T::deserialize(deserializer).map(Some)
+    }
+}
+
+impl MaterialDefinition {
+    // fills in everything this one leaves out from base, the base field itself is used up by this
+    fn over(&self, base: &MaterialDefinition) -> MaterialDefinition {
+        let textures = &self.textures;
+        MaterialDefinition {
+            base: None,
+            color: self.color.or(base.color),
+            emitance: self.emitance.or(base.emitance),
+            metalness: self.metalness.or(base.metalness),
+            roughness: self.roughness.or(base.roughness),
+            ior: self.ior.or(base.ior),
+            transmission: self.transmission.or(base.transmission),
+            absorption: self.absorption.or(base.absorption),
+            scattering: self.scattering.or(base.scattering),
+            anisotropy: self.anisotropy.or(base.anisotropy),
+            textures: MaterialTextures {
+                color: textures.color.clone().or(base.textures.color.clone()),
+                emitance: textures.emitance.clone().or(base.textures.emitance.clone()),
+                metalness: textures
+                    .metalness
+                    .clone()
+                    .or(base.textures.metalness.clone()),
+                roughness: textures
+                    .roughness
+                    .clone()
+                    .or(base.textures.roughness.clone()),
+                bump: textures.bump.clone().or(base.textures.bump.clone()),
+            },
+            bump_strength: self.bump_strength.or(base.bump_strength),
+            specular_ior: self.specular_ior.or(base.specular_ior),
+            specular_tint: self.specular_tint.or(base.specular_tint),
+            specular_anisotropy: self.specular_anisotropy.or(base.specular_anisotropy),
+            tangent: self.tangent.or(base.tangent),
+            clearcoat: self.clearcoat.or(base.clearcoat),
+            clearcoat_roughness: self.clearcoat_roughness.or(base.clearcoat_roughness),
+            sheen: self.sheen.or(base.sheen),
+            sheen_color: self.sheen_color.or(base.sheen_color),
+            sheen_roughness: self.sheen_roughness.or(base.sheen_roughness),
+        }
+    }
+
+    // follows the chain of bases all the way up and merges it into one definition without a base.
+    // chain holds the materials that are currently being flattened, running into one of them again means a cycle
+    fn flatten(
+        &self,
+        definitions: &HashMap<String, MaterialDefinition>,
+        chain: &mut Vec<String>,
+    ) -> Result<MaterialDefinition, String> {
+        let base_name = match &self.base {
+            Some(base_name) => base_name,
+            None => return Ok(self.clone()),
+        };
+        if chain.contains(base_name) {
+            chain.push(base_name.clone());
+            return Err(format!(
+                "materials inherit from each other in a cycle: {}",
+                chain.join(" -> ")
+            ));
+        }
+        let base = definitions.get(base_name).ok_or_else(|| {
+            format!(
+                "material \"{}\" has an unknown base \"{}\"",
+                chain.last().map_or("", |s| s.as_str()),
+                base_name
+            )
+        })?;
+        chain.push(base_name.clone());
+        let flattened_base = base.flatten(definitions, chain)?;
+        chain.pop();
+        Ok(self.over(&flattened_base))
+    }
+
+    // name is only used to make the errors readable
+    pub fn resolve(
+        &self,
+        name: &str,
+        definitions: &HashMap<String, MaterialDefinition>,
+    ) -> Result<Material, String> {
+        let flat = self.flatten(definitions, &mut vec![name.to_string()])?;
+        let required = |field: &str| {
+            format!(
+                "material \"{}\" doesn't set {} and doesn't inherit it from a base either",
+                name, field
+            )
+        };
+        Ok(Material {
+            color: flat.color.ok_or_else(|| required("color"))?,
+            emitance: flat.emitance.ok_or_else(|| required("emitance"))?,
+            metalness: flat.metalness.ok_or_else(|| required("metalness"))?,
+            roughness: flat.roughness.ok_or_else(|| required("roughness"))?,
+            ior: flat.ior.unwrap_or(1.5),
+            transmission: flat.transmission.unwrap_or(0.0),
+            absorption: flat.absorption.unwrap_or(BLACK),
+            scattering: flat.scattering.unwrap_or(BLACK),
+            anisotropy: flat.anisotropy.unwrap_or(0.0),
+            textures: flat.textures,
+            bump_strength: flat.bump_strength.unwrap_or(1.0),
+            bump: BLACK,
+            specular_ior: flat.specular_ior,
+            specular_tint: flat.specular_tint.unwrap_or(0.0),
+            specular_anisotropy: flat.specular_anisotropy.unwrap_or(0.0),
+            tangent: flat.tangent.unwrap_or(cgmath::vec3(1.0, 0.0, 0.0)),
+            clearcoat: flat.clearcoat.unwrap_or(0.0),
+            clearcoat_roughness: flat.clearcoat_roughness.unwrap_or(0.1),
+            sheen: flat.sheen.unwrap_or(0.0),
+            sheen_color: flat.sheen_color.unwrap_or(cgmath::vec3(1.0, 1.0, 1.0)),
+            sheen_roughness: flat.sheen_roughness.unwrap_or(0.5),
+        })
+    }
+}
+
+static BLACK: cgmath::Vector3<f64> = cgmath::vec3(0.0, 0.0, 0.0);
+
+pub fn resolve_materials(
+    definitions: &HashMap<String, MaterialDefinition>,
+) -> Result<HashMap<String, Material>, String> {
+    definitions
+        .iter()
+        .map(|(name, definition)| Ok((name.clone(), definition.resolve(name, definitions)?)))
+        .collect()
+}
+
+// what an object says its material is, either the name of one in the materials block or a material written right there
+#[derive(Clone)]
+pub enum MaterialRef {
+    Named(String),
+    Inline(Box<MaterialDefinition>),
+}
+
+impl MaterialRef {
+    pub fn name(&self) -> Option<&str> {
+        match self {
+            Self::Named(name) => Some(name),
+            Self::Inline(_) => None,
+        }
+    }
+}
+
+impl Serialize for MaterialRef {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        match self {
+            Self::Named(name) => serializer.serialize_str(name),
+            Self::Inline(definition) => definition.serialize(serializer),
+        }
+    }
+}
+
+// a string is a name and a struct is an inline material
+impl<'de> Deserialize<'de> for MaterialRef {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct RefVisitor;
+
+        impl<'de> serde::de::Visitor<'de> for RefVisitor {
+            type Value = MaterialRef;
+
+            fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+                write!(f, "the name of a material or a material")
+            }
+
+            fn visit_str<E: serde::de::Error>(self, name: &str) -> Result<MaterialRef, E> {
+                Ok(MaterialRef::Named(name.to_string()))
+            }
+
+            fn visit_map<A: serde::de::MapAccess<'de>>(
+                self,
+                map: A,
+            ) -> Result<MaterialRef, A::Error> {
+                MaterialDefinition::deserialize(serde::de::value::MapAccessDeserializer::new(map))
+                    .map(|definition| MaterialRef::Inline(Box::new(definition)))
+            }
+        }
+
+        deserializer.deserialize_any(RefVisitor)
+    }
+}
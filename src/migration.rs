@@ -0,0 +1,115 @@
+use std::{cell::RefCell, collections::BTreeSet};
+
+// the version of the scene format this build reads and writes. bump it whenever scenes change in a way
+// that older files would load wrong or not at all, and fix them up in the Upgrade impls.
+// fields that are new in a version don't need anything, they already fall back to their defaults
+pub static CURRENT_VERSION: u32 = 2;
+
+// how to read a file of some older version, and what got changed while doing so
+pub struct Migration {
+    from: u32,
+    // whether the file said which version it is
+    versioned: bool,
+    warnings: RefCell<BTreeSet<String>>,
+}
+
+impl Migration {
+    // version is what the file says it is, files that don't say are treated as version 1
+    pub fn new(version: Option<u32>) -> Result<Self, String> {
+        let from = match version {
+            Some(0) => return Err("there is no version 0".to_string()),
+            Some(v) if v > CURRENT_VERSION => {
+                return Err(format!(
+                    "it's version {} but this build only knows up to version {}",
+                    v, CURRENT_VERSION
+                ))
+            }
+            Some(v) => v,
+            None => 1,
+        };
+        Ok(Self {
+            from,
+            versioned: version.is_some(),
+            warnings: RefCell::new(BTreeSet::new()),
+        })
+    }
+
+    pub fn from(&self) -> u32 {
+        self.from
+    }
+
+    // for files that get upgraded as a whole, included files don't say anything unless something in them changes
+    pub fn warn_upgraded(&self) {
+        if !self.versioned {
+            self.warn("it has no version, so it's read as version 1".to_string());
+        }
+        if self.from < CURRENT_VERSION {
+            self.warn(format!(
+                "it was upgraded from version {} to {}, convert can save the upgraded file",
+                self.from, CURRENT_VERSION
+            ));
+        }
+    }
+
+    pub fn warn(&self, warning: String) {
+        self.warnings.borrow_mut().insert(warning);
+    }
+
+    pub fn into_warnings(self) -> Vec<String> {
+        self.warnings.into_inner().into_iter().collect()
+    }
+}
+
+// fix ups for older versions, they run on what was read from a file after it's been read
+pub trait Upgrade {
+    fn upgrade(&mut self, _migration: &Migration) {}
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel_drawer::World;
+
+    #[test]
+    fn unknown_versions_are_refused() {
+        assert!(Migration::new(Some(0)).is_err());
+        assert!(Migration::new(Some(CURRENT_VERSION + 1)).is_err());
+    }
+
+    #[test]
+    fn current_scenes_load_quietly() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("furnace.ron");
+        let mut world: World = ron::from_str(&std::fs::read_to_string(path).unwrap()).unwrap();
+        let migration = Migration::new(Some(CURRENT_VERSION)).unwrap();
+        world.upgrade(&migration);
+        assert!(migration.into_warnings().is_empty());
+        assert_eq!(world.sky_color, cgmath::vec3(1.0, 1.0, 1.0));
+    }
+
+    // the furnace scene without its version is a version 1 scene with a sky that never got drawn back then
+    #[test]
+    fn version_1_scenes_get_upgraded() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("furnace.ron");
+        let text = std::fs::read_to_string(path)
+            .unwrap()
+            .replace("version: 2,", "");
+        let mut world: World = ron::from_str(&text).unwrap();
+        let migration = Migration::new(None).unwrap();
+        world.upgrade(&migration);
+        assert_eq!(world.sky_color, cgmath::vec3(0.0, 0.0, 0.0));
+        let warnings = migration.into_warnings();
+        for expected in [
+            "it has no version".to_string(),
+            format!("upgraded from version 1 to {}", CURRENT_VERSION),
+            "sky_color was never drawn".to_string(),
+            "energy conserving".to_string(),
+        ] {
+            assert!(
+                warnings.iter().any(|w| w.contains(&expected)),
+                "no warning about {} in {:?}",
+                expected,
+                warnings
+            );
+        }
+    }
+}
@@ -0,0 +1,120 @@
+use std::collections::HashMap;
+
+use serde::Deserialize;
+
+use crate::expression::{self, Value};
+
+// an entry in a scene's params block, either a plain number or an expression that can use the other params
+#[derive(Deserialize)]
+#[serde(untagged)]
+pub enum ParamSource {
+    Number(f64),
+    Expression(String),
+}
+
+pub struct Params {
+    values: HashMap<String, Value>,
+}
+
+impl Params {
+    pub fn resolve(sources: &HashMap<String, ParamSource>) -> Result<Self, String> {
+        let mut values = HashMap::new();
+        for name in sources.keys() {
+            resolve_param(name, sources, &mut values, &mut vec![])?;
+        }
+        Ok(Self { values })
+    }
+
+    pub fn evaluate(&self, source: &str) -> Result<Value, String> {
+        expression::evaluate(source, &mut |name| {
+            self.values
+                .get(name)
+                .copied()
+                .ok_or_else(|| format!("unknown param {}", name))
+        })
+    }
+}
+
+// chain holds the params currently being worked out, so ones that depend on themselves get caught
+fn resolve_param(
+    name: &str,
+    sources: &HashMap<String, ParamSource>,
+    values: &mut HashMap<String, Value>,
+    chain: &mut Vec<String>,
+) -> Result<Value, String> {
+    if let Some(value) = values.get(name) {
+        return Ok(*value);
+    }
+    if chain.iter().any(|n| n == name) {
+        chain.push(name.to_string());
+        return Err(format!(
+            "params depend on themselves: {}",
+            chain.join(" -> ")
+        ));
+    }
+    let value = match sources.get(name) {
+        Some(ParamSource::Number(v)) => Value::Scalar(*v),
+        Some(ParamSource::Expression(source)) => {
+            chain.push(name.to_string());
+            let value = expression::evaluate(source, &mut |other| {
+                resolve_param(other, sources, values, chain)
+            })?;
+            chain.pop();
+            value
+        }
+        None => return Err(format!("unknown param {}", name)),
+    };
+    values.insert(name.to_string(), value);
+    Ok(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sources(entries: &[(&str, &str)]) -> HashMap<String, ParamSource> {
+        entries
+            .iter()
+            .map(|(name, source)| {
+                (
+                    name.to_string(),
+                    ParamSource::Expression(source.to_string()),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn params_use_each_other() {
+        let mut sources = sources(&[("diameter", "radius * 2"), ("offset", "(0, diameter, 1)")]);
+        sources.insert("radius".to_string(), ParamSource::Number(0.5));
+        let params = Params::resolve(&sources).unwrap();
+        assert_eq!(
+            params.evaluate("offset.y + radius").unwrap().scalar(),
+            Ok(1.5)
+        );
+    }
+
+    #[test]
+    fn cycles_are_caught() {
+        let sources = sources(&[("a", "b + 1"), ("b", "c * 2"), ("c", "a")]);
+        let error = Params::resolve(&sources).err().unwrap();
+        assert!(error.contains("params depend on themselves"), "{}", error);
+        // wherever it starts, the chain ends where it came back around
+        let chain = error.rsplit(": ").next().unwrap();
+        let names: Vec<_> = chain.split(" -> ").collect();
+        assert_eq!(names.len(), 4, "{}", error);
+        assert_eq!(names.first(), names.last());
+    }
+
+    #[test]
+    fn params_cant_use_themselves() {
+        let sources = sources(&[("a", "a + 1")]);
+        let error = Params::resolve(&sources).err().unwrap();
+        assert!(
+            error.ends_with("params depend on themselves: a -> a"),
+            "{}",
+            error
+        );
+    }
+}
@@ -1,34 +1,174 @@
 use std::{
-    collections::HashMap,
+    collections::{HashMap, HashSet},
     ops::DerefMut,
     sync::{Arc, Mutex},
-    thread::spawn,
+    thread::scope,
 };
 
 use cgmath::prelude::*;
-use rand_distr::Distribution;
 use rayon::iter::{ParallelBridge, ParallelIterator};
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize, Clone, Copy)]
+use crate::{
+    animation::{Animation, TransformTracks},
+    aov::{self, Aovs},
+    atmosphere::Atmosphere,
+    bsdf::{average, sample_bsdf, Frame, LOCAL_NORMAL},
+    camera::Camera,
+    debug::{distance_color, heatmap, DebugMode, Slice},
+    denoise::denoise,
+    library::load_includes,
+    lipschitz,
+    march::{Footprint, March},
+    material::{present, resolve_materials, MaterialDefinition, MaterialRef},
+    migration::{Migration, Upgrade, CURRENT_VERSION},
+    texture::{MaterialTextures, Texture},
+    tone_mapping::ToneMapping,
+};
+
+#[derive(Clone)]
 pub struct Material {
     pub color: cgmath::Vector3<f64>,
     pub emitance: cgmath::Vector3<f64>,
     pub metalness: f64,
     pub roughness: f64,
+    pub ior: f64,
+    // how much of the non metallic part lets light through instead of scattering it diffusely
+    pub transmission: f64,
+    // what happens to light travelling through the inside, both are per unit of distance
+    pub absorption: cgmath::Vector3<f64>,
+    pub scattering: cgmath::Vector3<f64>,
+    // henyey-greenstein g, positive scatters forwards and negative backwards
+    pub anisotropy: f64,
+    pub textures: MaterialTextures,
+    // how far the bump texture tilts the normal, 1 treats its values as heights in scene units
+    pub bump_strength: f64,
+    // filled in from the bump texture when the material gets evaluated, the slope of the surface it describes
+    pub bump: cgmath::Vector3<f64>,
+    // ior used for the strength of the reflections only, so they can be tuned without changing the refraction
+    pub specular_ior: Option<f64>,
+    // how much the non metallic reflections take on the color of the material
+    pub specular_tint: f64,
+    // 0 is round highlights, towards 1 they get stretched along the tangent
+    pub specular_anisotropy: f64,
+    // which way the highlights get stretched, it gets flattened onto the surface
+    pub tangent: cgmath::Vector3<f64>,
+    // a clear varnish layer on top of everything else, like car paint
+    pub clearcoat: f64,
+    pub clearcoat_roughness: f64,
+    // soft glow at grazing angles from fibers, like velvet
+    pub sheen: f64,
+    pub sheen_color: cgmath::Vector3<f64>,
+    pub sheen_roughness: f64,
 }
 
-#[derive(Serialize, Deserialize)]
+impl Material {
+    // gives back the material with its textures looked up at point, which is in the space of the object using it
+    fn evaluate(&self, point: cgmath::Point3<f64>, normal: cgmath::Vector3<f64>) -> Material {
+        let textures = &self.textures;
+        Material {
+            color: textures
+                .color
+                .as_ref()
+                .map_or(self.color, |t| t.evaluate(point, normal)),
+            emitance: textures
+                .emitance
+                .as_ref()
+                .map_or(self.emitance, |t| t.evaluate(point, normal)),
+            metalness: textures.metalness.as_ref().map_or(self.metalness, |t| {
+                t.evaluate_scalar(point, normal).clamp(0.0, 1.0)
+            }),
+            roughness: textures.roughness.as_ref().map_or(self.roughness, |t| {
+                t.evaluate_scalar(point, normal).clamp(0.0, 1.0)
+            }),
+            textures: MaterialTextures::NONE,
+            bump: textures.bump.as_ref().map_or(BLACK, |t| {
+                t.scalar_gradient(point, normal) * self.bump_strength
+            }),
+            ..*self
+        }
+    }
+}
+
+fn lookup_material(
+    object: &Object,
+    world: &World,
+    material: &MaterialRef,
+    point: cgmath::Point3<f64>,
+    time: f64,
+) -> Material {
+    let name = material.name();
+    let mut material = name
+        .and_then(|name| world.materials.get(name))
+        .unwrap_or(&BLACK_MATERIAL)
+        .clone();
+    if let Some(name) = name {
+        world.animation.animate_material(name, &mut material, time);
+    }
+    if material.textures.is_empty() {
+        return material;
+    }
+    let normal = if material.textures.needs_normal() {
+        object.local_normal(point, time)
+    } else {
+        BLACK
+    };
+    material.evaluate(object.texture_space(point), normal)
+}
+
+// where an instance ends up, the object gets scaled, then rotated (in degrees, around x then y then z) and then moved.
+// scaling is the same along every axis so distances stay distances
+#[derive(Serialize, Deserialize, Clone, Copy)]
+#[serde(default)]
+pub struct Transform {
+    pub translation: cgmath::Vector3<f64>,
+    pub rotation: cgmath::Vector3<f64>,
+    pub scale: f64,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Self {
+            translation: BLACK,
+            rotation: BLACK,
+            scale: 1.0,
+        }
+    }
+}
+
+impl Transform {
+    fn rotation_matrix(&self) -> cgmath::Matrix3<f64> {
+        cgmath::Matrix3::from(cgmath::Euler::new(
+            cgmath::Deg(self.rotation.x),
+            cgmath::Deg(self.rotation.y),
+            cgmath::Deg(self.rotation.z),
+        ))
+    }
+
+    fn apply(&self, point: cgmath::Point3<f64>) -> cgmath::Point3<f64> {
+        cgmath::Point3::from_vec(
+            self.rotation_matrix() * (point.to_vec() * self.scale) + self.translation,
+        )
+    }
+
+    fn inverse_apply(&self, point: cgmath::Point3<f64>) -> cgmath::Point3<f64> {
+        cgmath::Point3::from_vec(
+            self.rotation_matrix().transpose() * (point.to_vec() - self.translation) / self.scale,
+        )
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone)]
 pub enum Object {
     Sphere {
         center: cgmath::Point3<f64>,
         radius: f64,
-        material: String,
+        material: MaterialRef,
     },
     Box {
         lower_corner: cgmath::Point3<f64>,
         upper_corner: cgmath::Point3<f64>,
-        material: String,
+        material: MaterialRef,
     },
     PosModulo(Box<Object>, f64),
     Inv(Box<Object>),
@@ -38,12 +178,39 @@ pub enum Object {
         major_radius: f64,
         minor_radius: f64,
         center: cgmath::Point3<f64>,
-        material: String,
+        material: MaterialRef,
     },
     Smooth {
         alpha: f64,
         objects: Vec<Object>,
     },
+    // pushes the surface out by amount times the texture, the distance gets scaled down by how steep
+    // the texture is so the marcher can't step through the bumps
+    Displace {
+        object: Box<Object>,
+        texture: Texture,
+        amount: f64,
+        // how fast the texture can change per unit, worked out from the texture when loading if not given.
+        // textures with hard edges like Checker and images need it set
+        #[serde(default, with = "present", skip_serializing_if = "Option::is_none")]
+        max_slope: Option<f64>,
+    },
+    // a copy of one of the scene's definitions, gets swapped out for the Transformed object it stands for when loading
+    Instance(String, Transform),
+    Transformed(Box<Object>, Transform),
+    // an object that moves over time
+    Animated {
+        object: Box<Object>,
+        tracks: TransformTracks,
+    },
+    // for objects whose distance estimate can come out bigger than the real distance, like Smooth with a negative
+    // alpha, PosModulo or cutting things out with Inv, which lets rays step right through surfaces.
+    // rays take steps factor times shorter, it gets worked out by sampling the object when loading if it isn't given
+    Lipschitz {
+        object: Box<Object>,
+        #[serde(default, with = "present", skip_serializing_if = "Option::is_none")]
+        factor: Option<f64>,
+    },
 }
 
 fn smooth(values: &[f64], alpha: f64) -> (f64, Vec<f64>) {
@@ -69,7 +236,37 @@ where
 }
 
 impl Object {
-    fn estimate_distance(&self, point: cgmath::Point3<f64>) -> f64 {
+    fn local_normal(&self, point: cgmath::Point3<f64>, time: f64) -> cgmath::Vector3<f64> {
+        let e = 0.001;
+        cgmath::vec3(
+            self.estimate_distance(point + cgmath::vec3(e, 0.0, 0.0), time)
+                - self.estimate_distance(point - cgmath::vec3(e, 0.0, 0.0), time),
+            self.estimate_distance(point + cgmath::vec3(0.0, e, 0.0), time)
+                - self.estimate_distance(point - cgmath::vec3(0.0, e, 0.0), time),
+            self.estimate_distance(point + cgmath::vec3(0.0, 0.0, e), time)
+                - self.estimate_distance(point - cgmath::vec3(0.0, 0.0, e), time),
+        )
+        .normalize()
+    }
+
+    // point is already in the space of whatever Transformed or Animated the object is in,
+    // textures are centered on the shape itself on top of that so moving it takes them along
+    fn texture_space(&self, point: cgmath::Point3<f64>) -> cgmath::Point3<f64> {
+        let origin = match self {
+            Self::Sphere { center, .. } | Self::Torus { center, .. } => *center,
+            Self::Box {
+                lower_corner,
+                upper_corner,
+                ..
+            } => lower_corner.midpoint(*upper_corner),
+            Self::Displace { object, .. } => return object.texture_space(point),
+            _ => return point,
+        };
+        cgmath::Point3::from_vec(point - origin)
+    }
+
+    // time is in seconds, it's what animated objects move with
+    fn estimate_distance(&self, point: cgmath::Point3<f64>, time: f64) -> f64 {
         match self {
             Self::Sphere { center, radius, .. } => point.distance(*center) - radius,
             Self::Box {
@@ -84,10 +281,16 @@ impl Object {
                 q.map(|x| x.max(0.0)).distance(cgmath::vec3(0.0, 0.0, 0.0))
                     + q.x.max(q.y.max(q.z)).min(0.0)
             }
-            Self::PosModulo(o, period) => o.estimate_distance(point.map(|x| x.rem_euclid(*period))),
-            Self::Inv(o) => -o.estimate_distance(point),
-            Self::Max(a, b) => a.estimate_distance(point).max(b.estimate_distance(point)),
-            Self::Min(a, b) => a.estimate_distance(point).min(b.estimate_distance(point)),
+            Self::PosModulo(o, period) => {
+                o.estimate_distance(point.map(|x| x.rem_euclid(*period)), time)
+            }
+            Self::Inv(o) => -o.estimate_distance(point, time),
+            Self::Max(a, b) => a
+                .estimate_distance(point, time)
+                .max(b.estimate_distance(point, time)),
+            Self::Min(a, b) => a
+                .estimate_distance(point, time)
+                .min(b.estimate_distance(point, time)),
             Self::Torus {
                 major_radius,
                 minor_radius,
@@ -106,37 +309,426 @@ impl Object {
                 point.magnitude() - minor_radius
             }
             Self::Smooth { alpha, objects } => {
-                let distances: Vec<_> =
-                    objects.iter().map(|o| o.estimate_distance(point)).collect();
+                let distances: Vec<_> = objects
+                    .iter()
+                    .map(|o| o.estimate_distance(point, time))
+                    .collect();
                 smooth(&distances, *alpha).0
             }
+            Self::Displace {
+                object,
+                texture,
+                amount,
+                max_slope,
+            } => {
+                let normal = if texture.needs_normal() {
+                    object.local_normal(point, time)
+                } else {
+                    BLACK
+                };
+                let height = texture.evaluate_scalar(object.texture_space(point), normal);
+                // filled in when loading
+                let slope = max_slope.unwrap_or(f64::INFINITY);
+                (object.estimate_distance(point, time) - amount * height)
+                    / (1.0 + amount.abs() * slope)
+            }
+            // there is nothing there until the instance has been expanded
+            Self::Instance(..) => f64::INFINITY,
+            Self::Transformed(o, transform) => o.transformed_distance(transform, point, time),
+            Self::Animated { object, tracks } => {
+                object.transformed_distance(&tracks.at(time), point, time)
+            }
+            // the factor only shortens the steps cast_ray takes, so the objects around this one see the same distance
+            Self::Lipschitz { object, .. } => object.estimate_distance(point, time),
+        }
+    }
+
+    fn transformed_distance(
+        &self,
+        transform: &Transform,
+        point: cgmath::Point3<f64>,
+        time: f64,
+    ) -> f64 {
+        self.estimate_distance(transform.inverse_apply(point), time) * transform.scale
+    }
+
+    pub fn load_images(&mut self, base: &std::path::Path) -> Result<(), String> {
+        match self {
+            Self::Sphere { .. } | Self::Box { .. } | Self::Torus { .. } | Self::Instance(..) => {
+                Ok(())
+            }
+            Self::PosModulo(o, _)
+            | Self::Inv(o)
+            | Self::Transformed(o, _)
+            | Self::Animated { object: o, .. }
+            | Self::Lipschitz { object: o, .. } => o.load_images(base),
+            Self::Min(a, b) | Self::Max(a, b) => {
+                a.load_images(base)?;
+                b.load_images(base)
+            }
+            Self::Smooth { objects, .. } => {
+                for o in objects {
+                    o.load_images(base)?;
+                }
+                Ok(())
+            }
+            Self::Displace {
+                object,
+                texture,
+                max_slope,
+                ..
+            } => {
+                object.load_images(base)?;
+                texture.load_images(base)?;
+                // worked out once here, it takes walking the whole texture
+                if max_slope.is_none() {
+                    let slope = texture.max_slope();
+                    if !slope.is_finite() {
+                        return Err(
+                            "Displace with a texture that has hard edges or is an image needs max_slope set"
+                                .to_string(),
+                        );
+                    }
+                    *max_slope = Some(slope);
+                }
+                Ok(())
+            }
+        }
+    }
+
+    fn children(&self) -> Vec<&Object> {
+        match self {
+            Self::Sphere { .. } | Self::Box { .. } | Self::Torus { .. } | Self::Instance(..) => {
+                vec![]
+            }
+            Self::PosModulo(o, _)
+            | Self::Inv(o)
+            | Self::Displace { object: o, .. }
+            | Self::Transformed(o, _)
+            | Self::Animated { object: o, .. }
+            | Self::Lipschitz { object: o, .. } => vec![o],
+            Self::Min(a, b) | Self::Max(a, b) => vec![a, b],
+            Self::Smooth { objects, .. } => objects.iter().collect(),
+        }
+    }
+
+    fn children_mut(&mut self) -> Vec<&mut Object> {
+        match self {
+            Self::Sphere { .. } | Self::Box { .. } | Self::Torus { .. } | Self::Instance(..) => {
+                vec![]
+            }
+            Self::PosModulo(o, _)
+            | Self::Inv(o)
+            | Self::Displace { object: o, .. }
+            | Self::Transformed(o, _)
+            | Self::Animated { object: o, .. }
+            | Self::Lipschitz { object: o, .. } => vec![o],
+            Self::Min(a, b) | Self::Max(a, b) => vec![a, b],
+            Self::Smooth { objects, .. } => objects.iter_mut().collect(),
+        }
+    }
+
+    // every material reference in the tree, for filling in the inline ones
+    pub fn material_refs_mut(&mut self) -> Vec<&mut MaterialRef> {
+        match self {
+            Self::Sphere { material, .. }
+            | Self::Box { material, .. }
+            | Self::Torus { material, .. } => vec![material],
+            _ => self
+                .children_mut()
+                .into_iter()
+                .flat_map(|o| o.material_refs_mut())
+                .collect(),
+        }
+    }
+
+    // replaces every Instance in the tree with a copy of what it refers to.
+    // chain holds the definitions currently being expanded, so one that contains itself gets caught
+    fn expand_instances(
+        &mut self,
+        definitions: &HashMap<String, Object>,
+        chain: &mut Vec<String>,
+    ) -> Result<(), String> {
+        if let Self::Instance(name, transform) = self {
+            if chain.contains(name) {
+                chain.push(name.clone());
+                return Err(format!(
+                    "definitions contain themselves: {}",
+                    chain.join(" -> ")
+                ));
+            }
+            let mut object = definitions
+                .get(name)
+                .ok_or_else(|| format!("instance of unknown definition \"{}\"", name))?
+                .clone();
+            chain.push(name.clone());
+            object.expand_instances(definitions, chain)?;
+            chain.pop();
+            *self = Self::Transformed(Box::new(object), *transform);
+            return Ok(());
+        }
+        for child in self.children_mut() {
+            child.expand_instances(definitions, chain)?;
+        }
+        Ok(())
+    }
+
+    fn check_animation(&mut self) -> Result<(), String> {
+        if let Self::Animated { tracks, .. } = self {
+            tracks.check()?;
+        }
+        for child in self.children_mut() {
+            child.check_animation()?;
+        }
+        Ok(())
+    }
+
+    // fills in the factors of Lipschitz objects that don't have one, inner ones first so the outer ones
+    // get measured with them in place. times is the stretch animated objects get sampled over
+    fn estimate_lipschitz(&mut self, times: std::ops::Range<f64>) -> Result<(), String> {
+        for child in self.children_mut() {
+            child.estimate_lipschitz(times.clone())?;
+        }
+        if let Self::Lipschitz { object, factor } = self {
+            match factor {
+                Some(factor) if *factor <= 0.0 => {
+                    return Err("Lipschitz factor has to be above 0".to_string())
+                }
+                Some(_) => {}
+                None => {
+                    let region = object.sample_region(times.clone(), lipschitz::EXTENT);
+                    let report = lipschitz::check(
+                        |point, time| object.estimate_distance(point, time),
+                        times,
+                        lipschitz::SAMPLES,
+                        region,
+                    );
+                    *factor = Some(report.factor());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    // how many times shorter rays have to step for this to be safe, which is the biggest factor of any
+    // Lipschitz in it. Lipschitz leaves the estimate as it is, so the factors all bound the same estimate
+    // and the biggest one covers the others
+    pub fn step_scale(&self) -> f64 {
+        let own = match self {
+            Self::Lipschitz { factor, .. } => factor.unwrap_or(1.0),
+            _ => 1.0,
+        };
+        self.children()
+            .into_iter()
+            .map(Object::step_scale)
+            .fold(own, f64::max)
+    }
+
+    // whether anything in it moves, if not time makes no difference to it
+    pub fn is_animated(&self) -> bool {
+        matches!(self, Self::Animated { .. })
+            || self.children().into_iter().any(Object::is_animated)
+    }
+
+    // a sphere that the object stays inside of over times, as its center and radius.
+    // there's none for the ones that go on forever
+    fn bounds(&self, times: std::ops::Range<f64>) -> Option<(cgmath::Point3<f64>, f64)> {
+        fn union(
+            (a, a_radius): (cgmath::Point3<f64>, f64),
+            (b, b_radius): (cgmath::Point3<f64>, f64),
+        ) -> (cgmath::Point3<f64>, f64) {
+            let apart = a.distance(b);
+            if apart + b_radius <= a_radius {
+                return (a, a_radius);
+            }
+            if apart + a_radius <= b_radius {
+                return (b, b_radius);
+            }
+            let radius = (apart + a_radius + b_radius) / 2.0;
+            (a + (b - a) * ((radius - a_radius) / apart), radius)
+        }
+        match self {
+            Self::Sphere { center, radius, .. } => Some((*center, radius.abs())),
+            Self::Box {
+                lower_corner,
+                upper_corner,
+                ..
+            } => Some((
+                lower_corner.midpoint(*upper_corner),
+                lower_corner.distance(*upper_corner) / 2.0,
+            )),
+            Self::Torus {
+                major_radius,
+                minor_radius,
+                center,
+                ..
+            } => Some((*center, major_radius.abs() + minor_radius.abs())),
+            Self::PosModulo(..) | Self::Inv(..) | Self::Instance(..) => None,
+            Self::Min(a, b) => Some(union(a.bounds(times.clone())?, b.bounds(times)?)),
+            // only what's in both is left, so either one will do
+            Self::Max(a, b) => match (a.bounds(times.clone()), b.bounds(times)) {
+                (Some(a), Some(b)) => Some(if a.1 < b.1 { a } else { b }),
+                (a, b) => a.or(b),
+            },
+            // the blend can reach a bit past all of them, but not by more than ln(n) / alpha
+            Self::Smooth { alpha, objects } => {
+                let (center, radius) = objects
+                    .iter()
+                    .map(|o| o.bounds(times.clone()))
+                    .reduce(|a, b| Some(union(a?, b?)))??;
+                let reach = (objects.len() as f64).ln() / alpha.abs();
+                reach.is_finite().then_some((center, radius + reach))
+            }
+            Self::Displace {
+                object,
+                texture,
+                amount,
+                ..
+            } => {
+                let (center, radius) = object.bounds(times)?;
+                let (low, high) = texture.range();
+                Some((
+                    center,
+                    radius + (amount * low).abs().max((amount * high).abs()),
+                ))
+            }
+            Self::Transformed(o, transform) => {
+                let (center, radius) = o.bounds(times)?;
+                Some((transform.apply(center), radius * transform.scale.abs()))
+            }
+            // going by where it is at a few moments, which is close enough for picking where to sample
+            Self::Animated { object, tracks } => {
+                let (center, radius) = object.bounds(times.clone())?;
+                (0..=16)
+                    .map(|i| {
+                        let time = times.start + (times.end - times.start) * i as f64 / 16.0;
+                        let transform = tracks.at(time);
+                        (transform.apply(center), radius * transform.scale.abs())
+                    })
+                    .reduce(union)
+            }
+            Self::Lipschitz { object, .. } => object.bounds(times),
+        }
+    }
+
+    // where to look for places the estimate is too big, around the object with some room to spare
+    // or extent around the origin for the ones that go on forever
+    pub fn sample_region(
+        &self,
+        times: std::ops::Range<f64>,
+        extent: f64,
+    ) -> (cgmath::Point3<f64>, f64) {
+        self.bounds(times)
+            .map(|(center, radius)| (center, (radius * 2.0).max(0.1)))
+            .unwrap_or((cgmath::point3(0.0, 0.0, 0.0), extent))
+    }
+
+    // what sort of object it is, for messages
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Sphere { .. } => "Sphere",
+            Self::Box { .. } => "Box",
+            Self::PosModulo(..) => "PosModulo",
+            Self::Inv(..) => "Inv",
+            Self::Min(..) => "Min",
+            Self::Max(..) => "Max",
+            Self::Torus { .. } => "Torus",
+            Self::Smooth { .. } => "Smooth",
+            Self::Displace { .. } => "Displace",
+            Self::Instance(..) => "Instance",
+            Self::Transformed(..) => "Transformed",
+            Self::Animated { .. } => "Animated",
+            Self::Lipschitz { .. } => "Lipschitz",
+        }
+    }
+
+    // the material of whichever part of the object point is closest to, so materials can be told apart
+    // in the aovs even where they're blended
+    fn closest_material(&self, point: cgmath::Point3<f64>, time: f64) -> (f64, Option<&str>) {
+        fn closer<'a>(
+            a: (f64, Option<&'a str>),
+            b: (f64, Option<&'a str>),
+            smaller: bool,
+        ) -> (f64, Option<&'a str>) {
+            if (a.0 < b.0) == smaller {
+                a
+            } else {
+                b
+            }
+        }
+        match self {
+            Self::Sphere { material, .. }
+            | Self::Box { material, .. }
+            | Self::Torus { material, .. } => {
+                (self.estimate_distance(point, time), material.name())
+            }
+            Self::PosModulo(o, period) => {
+                o.closest_material(point.map(|x| x.rem_euclid(*period)), time)
+            }
+            Self::Inv(o) => {
+                let (dist, material) = o.closest_material(point, time);
+                (-dist, material)
+            }
+            Self::Min(a, b) => closer(
+                a.closest_material(point, time),
+                b.closest_material(point, time),
+                true,
+            ),
+            Self::Max(a, b) => closer(
+                a.closest_material(point, time),
+                b.closest_material(point, time),
+                false,
+            ),
+            // a negative alpha blends like Min, a positive one like Max
+            Self::Smooth { alpha, objects } => objects
+                .iter()
+                .map(|o| o.closest_material(point, time))
+                .reduce(|a, b| closer(a, b, *alpha < 0.0))
+                .unwrap_or((f64::INFINITY, None)),
+            Self::Displace { object, .. } => (
+                self.estimate_distance(point, time),
+                object.closest_material(point, time).1,
+            ),
+            Self::Instance(..) => (f64::INFINITY, None),
+            Self::Transformed(o, transform) => {
+                let (dist, material) = o.closest_material(transform.inverse_apply(point), time);
+                (dist * transform.scale, material)
+            }
+            Self::Animated { object, tracks } => {
+                let transform = tracks.at(time);
+                let (dist, material) =
+                    object.closest_material(transform.inverse_apply(point), time);
+                (dist * transform.scale, material)
+            }
+            Self::Lipschitz { object, .. } => object.closest_material(point, time),
         }
     }
 
     fn get_metadata(
         &self,
         point: cgmath::Point3<f64>,
-        material_lookup: &HashMap<String, Material>,
+        time: f64,
+        world: &World,
     ) -> (f64, Material) {
         match self {
             Self::Sphere { material, .. } => (
-                self.estimate_distance(point),
-                *material_lookup.get(material).unwrap_or(&BLACK_MATERIAL),
+                self.estimate_distance(point, time),
+                lookup_material(self, world, material, point, time),
             ),
             Self::Box { material, .. } => (
-                self.estimate_distance(point),
-                *material_lookup.get(material).unwrap_or(&BLACK_MATERIAL),
+                self.estimate_distance(point, time),
+                lookup_material(self, world, material, point, time),
             ),
             Self::PosModulo(o, period) => {
-                o.get_metadata(point.map(|x| x.rem_euclid(*period)), material_lookup)
+                o.get_metadata(point.map(|x| x.rem_euclid(*period)), time, world)
             }
             Self::Inv(o) => {
-                let (dist, meta) = o.get_metadata(point, material_lookup);
+                let (dist, meta) = o.get_metadata(point, time, world);
                 (-dist, meta)
             }
             Object::Min(a, b) => {
-                let (a_dist, a_meta) = a.get_metadata(point, material_lookup);
-                let (b_dist, b_meta) = b.get_metadata(point, material_lookup);
+                let (a_dist, a_meta) = a.get_metadata(point, time, world);
+                let (b_dist, b_meta) = b.get_metadata(point, time, world);
                 if a_dist < b_dist {
                     (a_dist, a_meta)
                 } else {
@@ -144,8 +736,8 @@ impl Object {
                 }
             }
             Object::Max(a, b) => {
-                let (a_dist, a_meta) = a.get_metadata(point, material_lookup);
-                let (b_dist, b_meta) = b.get_metadata(point, material_lookup);
+                let (a_dist, a_meta) = a.get_metadata(point, time, world);
+                let (b_dist, b_meta) = b.get_metadata(point, time, world);
                 if a_dist > b_dist {
                     (a_dist, a_meta)
                 } else {
@@ -153,13 +745,25 @@ impl Object {
                 }
             }
             Self::Torus { material, .. } => (
-                self.estimate_distance(point),
-                *material_lookup.get(material).unwrap_or(&BLACK_MATERIAL),
+                self.estimate_distance(point, time),
+                lookup_material(self, world, material, point, time),
             ),
+            Self::Displace { object, .. } => (
+                self.estimate_distance(point, time),
+                object.get_metadata(point, time, world).1,
+            ),
+            Self::Instance(..) => (f64::INFINITY, BLACK_MATERIAL.clone()),
+            Self::Transformed(o, transform) => {
+                o.transformed_metadata(transform, point, time, world)
+            }
+            Self::Animated { object, tracks } => {
+                object.transformed_metadata(&tracks.at(time), point, time, world)
+            }
+            Self::Lipschitz { object, .. } => object.get_metadata(point, time, world),
             Self::Smooth { alpha, objects } => {
                 let materials: Vec<_> = objects
                     .iter()
-                    .map(|o| o.get_metadata(point, material_lookup))
+                    .map(|o| o.get_metadata(point, time, world))
                     .collect();
                 let distances: Vec<_> = materials.iter().map(|(d, _)| *d).collect();
                 let (final_distance, mut exp_terms) = smooth(&distances, *alpha);
@@ -169,58 +773,147 @@ impl Object {
                 }
                 let bottom_sum = exp_terms.iter().sum();
 
-                let roughness = for_single(
-                    materials
-                        .iter()
-                        .map(|(_, m)| m.roughness)
-                        .collect::<Vec<_>>(),
-                    bottom_sum,
-                    &exp_terms,
-                )
-                .clamp(0.0, 1.0);
-                let metalness = for_single(
-                    materials
-                        .iter()
-                        .map(|(_, m)| m.metalness)
-                        .collect::<Vec<_>>(),
-                    bottom_sum,
-                    &exp_terms,
-                )
-                .clamp(0.0, 1.0);
-                let color = for_single(
-                    materials.iter().map(|(_, m)| m.color).collect::<Vec<_>>(),
-                    bottom_sum,
-                    &exp_terms,
-                );
-                let emitance = for_single(
-                    materials
-                        .iter()
-                        .map(|(_, m)| m.emitance)
-                        .collect::<Vec<_>>(),
-                    bottom_sum,
-                    &exp_terms,
-                );
+                let blend = |field: fn(&Material) -> f64| {
+                    for_single(
+                        materials.iter().map(|(_, m)| field(m)).collect::<Vec<_>>(),
+                        bottom_sum,
+                        &exp_terms,
+                    )
+                };
+                let blend_color = |field: fn(&Material) -> cgmath::Vector3<f64>| {
+                    for_single(
+                        materials.iter().map(|(_, m)| field(m)).collect::<Vec<_>>(),
+                        bottom_sum,
+                        &exp_terms,
+                    )
+                };
+                // only blended when one of them actually sets it, otherwise it keeps falling back to the ior
+                let specular_ior = if materials.iter().any(|(_, m)| m.specular_ior.is_some()) {
+                    Some(blend(|m| m.specular_ior.unwrap_or(m.ior)).max(1.0))
+                } else {
+                    None
+                };
 
                 (
                     final_distance,
                     Material {
-                        color,
-                        emitance,
-                        metalness,
-                        roughness,
+                        color: blend_color(|m| m.color),
+                        emitance: blend_color(|m| m.emitance),
+                        metalness: blend(|m| m.metalness).clamp(0.0, 1.0),
+                        roughness: blend(|m| m.roughness).clamp(0.0, 1.0),
+                        ior: blend(|m| m.ior).max(1.0),
+                        transmission: blend(|m| m.transmission).clamp(0.0, 1.0),
+                        absorption: blend_color(|m| m.absorption),
+                        scattering: blend_color(|m| m.scattering),
+                        anisotropy: blend(|m| m.anisotropy).clamp(-0.99, 0.99),
+                        textures: MaterialTextures::NONE,
+                        bump_strength: blend(|m| m.bump_strength),
+                        bump: blend_color(|m| m.bump),
+                        specular_ior,
+                        specular_tint: blend(|m| m.specular_tint).clamp(0.0, 1.0),
+                        specular_anisotropy: blend(|m| m.specular_anisotropy).clamp(0.0, 1.0),
+                        tangent: blend_color(|m| m.tangent),
+                        clearcoat: blend(|m| m.clearcoat).clamp(0.0, 1.0),
+                        clearcoat_roughness: blend(|m| m.clearcoat_roughness).clamp(0.0, 1.0),
+                        sheen: blend(|m| m.sheen).clamp(0.0, 1.0),
+                        sheen_color: blend_color(|m| m.sheen_color),
+                        sheen_roughness: blend(|m| m.sheen_roughness).clamp(0.0, 1.0),
                     },
                 )
             }
         }
     }
+
+    fn transformed_metadata(
+        &self,
+        transform: &Transform,
+        point: cgmath::Point3<f64>,
+        time: f64,
+        world: &World,
+    ) -> (f64, Material) {
+        let (dist, mut meta) = self.get_metadata(transform.inverse_apply(point), time, world);
+        // the bump slope and the tangent come out in the space of the object, so they have to be turned with it
+        let rotation = transform.rotation_matrix();
+        meta.bump = rotation * meta.bump / transform.scale;
+        meta.tangent = rotation * meta.tangent;
+        (dist * transform.scale, meta)
+    }
 }
 
 #[derive(Serialize, Deserialize)]
 pub struct World {
+    // the scene format version, read ahead of everything else by scene_file. anything read is upgraded
+    // to the current version, so that's what gets written back out
+    #[serde(skip_deserializing, default = "current_version")]
+    pub version: u32,
     pub max_ray_depth: u32,
+    // bounces before russian roulette is allowed to end a path, max_ray_depth still cuts it off no matter what
+    #[serde(default = "default_russian_roulette_depth")]
+    pub russian_roulette_depth: u32,
     pub sky_color: cgmath::Vector3<f64>,
     pub objects: Vec<Object>,
+    // other files to pull materials, definitions and objects from, relative to this one
+    #[serde(default)]
+    pub include: Vec<String>,
+    // named objects that can be put into the scene any number of times with Instance
+    #[serde(default)]
+    pub definitions: HashMap<String, Object>,
+    #[serde(rename = "materials")]
+    pub material_definitions: HashMap<String, MaterialDefinition>,
+    // the definitions with their inheritance worked out, plus the inline ones from objects. filled in by prepare
+    #[serde(skip)]
     pub materials: HashMap<String, Material>,
+    #[serde(default)]
+    pub tone_mapping: ToneMapping,
+    #[serde(default)]
+    pub atmosphere: Atmosphere,
+    #[serde(default)]
+    pub camera: Camera,
+    #[serde(default)]
+    pub animation: Animation,
+    #[serde(default)]
+    pub march: March,
+    // when the frame being rendered starts, in seconds. rays are spread out over the shutter from there
+    #[serde(skip)]
+    pub time: f64,
+    // where autofocus found something at the start of the frame
+    #[serde(skip)]
+    pub autofocus_distance: Option<f64>,
+    // the numbers materials get in the aovs, in order of their names
+    #[serde(skip)]
+    pub material_ids: HashMap<String, u32>,
+    // how many times shorter than their distance estimates rays step for each object, from their Lipschitz factors
+    #[serde(skip)]
+    pub step_scales: Vec<f64>,
+}
+
+fn default_russian_roulette_depth() -> u32 {
+    3
+}
+
+fn current_version() -> u32 {
+    CURRENT_VERSION
+}
+
+impl Upgrade for World {
+    fn upgrade(&mut self, migration: &Migration) {
+        migration.warn_upgraded();
+        if migration.from() < 2 {
+            // rays that missed everything used to come back black, sky_color wasn't used for anything
+            if self.sky_color != BLACK {
+                self.sky_color = BLACK;
+                migration.warn(
+                    "sky_color was never drawn before version 2, so it's set to black to keep the scene looking the same"
+                        .to_string(),
+                );
+            }
+            migration.warn(
+                "bounced light used to be scaled up by a made up factor and is energy conserving since version 2, \
+                 so emitance may need turning up to match the old look"
+                    .to_string(),
+            );
+        }
+    }
 }
 
 static BLACK: cgmath::Vector3<f64> = cgmath::vec3(0.0, 0.0, 0.0);
@@ -229,33 +922,190 @@ static BLACK_MATERIAL: Material = Material {
     emitance: BLACK,
     metalness: 0.0,
     roughness: 1.0,
+    ior: 1.5,
+    transmission: 0.0,
+    absorption: BLACK,
+    scattering: BLACK,
+    anisotropy: 0.0,
+    textures: MaterialTextures::NONE,
+    bump_strength: 1.0,
+    bump: BLACK,
+    specular_ior: None,
+    specular_tint: 0.0,
+    specular_anisotropy: 0.0,
+    tangent: cgmath::vec3(1.0, 0.0, 0.0),
+    clearcoat: 0.0,
+    clearcoat_roughness: 0.1,
+    sheen: 0.0,
+    sheen_color: cgmath::vec3(1.0, 1.0, 1.0),
+    sheen_roughness: 0.5,
 };
 
 impl World {
-    fn estimate_distance(&self, point: cgmath::Point3<f64>) -> f64 {
+    // sets up everything the scene file at path only refers to, like the pictures for image textures (relative to it),
+    // and checks the things deserializing can't
+    pub fn prepare(&mut self, path: &std::path::Path) -> Result<(), String> {
+        let base = path.parent().unwrap_or_else(|| std::path::Path::new("."));
+        // the scene itself counts as already loaded, so an include that leads back to it doesn't add it twice
+        let mut visited = HashSet::new();
+        if let Ok(canonical) = path.canonicalize() {
+            visited.insert(canonical);
+        }
+        // the scene's own materials and definitions win over included ones with the same name
+        let library = load_includes(&self.include, base, &mut visited)?;
+        for (name, material) in library.materials {
+            self.material_definitions.entry(name).or_insert(material);
+        }
+        for (name, definition) in library.definitions {
+            self.definitions.entry(name).or_insert(definition);
+        }
+        self.objects.splice(0..0, library.objects);
+        for object in &mut self.objects {
+            object.expand_instances(&self.definitions, &mut vec![])?;
+        }
+
+        self.materials = resolve_materials(&self.material_definitions)?;
+        // inline materials get a made up name so they can be looked up like any other
+        let mut definitions = self.material_definitions.clone();
+        let mut inline_count = 0;
+        for object in &mut self.objects {
+            for material in object.material_refs_mut() {
+                if let MaterialRef::Inline(definition) = material {
+                    let resolved =
+                        definition.resolve("inline material", &self.material_definitions)?;
+                    let name = format!("#inline {}", inline_count);
+                    inline_count += 1;
+                    self.materials.insert(name.clone(), resolved);
+                    definitions.insert(name.clone(), (**definition).clone());
+                    *material = MaterialRef::Named(name);
+                }
+            }
+        }
+        for material in self.materials.values_mut() {
+            material.textures.load_images(base)?;
+        }
+        for object in &mut self.objects {
+            object.load_images(base)?;
+            object.check_animation()?;
+        }
+        self.animation.check(&self.materials)?;
+        self.animation.inherit_material_tracks(&definitions);
+        self.march.check()?;
+        let times = self.animation_times();
+        for object in &mut self.objects {
+            object.estimate_lipschitz(times.clone())?;
+        }
+        self.step_scales = self.objects.iter().map(Object::step_scale).collect();
+        let mut names: Vec<_> = self.materials.keys().cloned().collect();
+        names.sort();
+        self.material_ids = names.into_iter().zip(0..).collect();
+        self.set_time(self.animation.time_of_frame(self.animation.start));
+        Ok(())
+    }
+
+    // from the start of the animation to its end, in seconds
+    fn animation_times(&self) -> std::ops::Range<f64> {
+        self.animation.time_of_frame(self.animation.start)
+            ..self.animation.time_of_frame(self.animation.end)
+    }
+
+    // samples the distance estimate of every object for places where rays could step through a surface,
+    // with the steps shortened by whatever Lipschitz it has in it. objects that go on forever get points
+    // picked up to extent away from the origin
+    pub fn check_bounds(&self, samples: u32, extent: f64) -> Vec<lipschitz::Report> {
         self.objects
             .iter()
-            .map(|x| x.estimate_distance(point))
+            .map(|object| {
+                let scale = object.step_scale();
+                lipschitz::check(
+                    |point, time| object.estimate_distance(point, time) / scale,
+                    self.animation_times(),
+                    samples,
+                    object.sample_region(self.animation_times(), extent),
+                )
+            })
+            .collect()
+    }
+
+    pub fn set_time(&mut self, time: f64) {
+        self.time = time;
+        self.autofocus_distance = self.autofocus(time);
+    }
+
+    // focuses on whatever a ray straight through the middle of the picture hits, if it hits anything
+    fn autofocus(&self, time: f64) -> Option<f64> {
+        let camera = self.animation.camera_at(self.camera, time);
+        if !camera.autofocus {
+            return None;
+        }
+        let ray = cast_ray(
+            camera.position,
+            camera.forward(),
+            Footprint::default(),
+            self,
+            false,
+            time,
+        );
+        ray.hit_anything
+            .then(|| camera.position.distance(ray.position))
+    }
+
+    // picks a moment for a ray while the shutter is open
+    fn sample_time<T: rand::Rng>(&self, rand: &mut T) -> f64 {
+        self.time + rand.gen::<f64>() * self.animation.shutter / self.animation.fps
+    }
+
+    fn estimate_distance(&self, point: cgmath::Point3<f64>, time: f64) -> f64 {
+        self.objects
+            .iter()
+            .map(|x| x.estimate_distance(point, time))
             .reduce(f64::min)
             .unwrap_or(0.0)
     }
 
-    fn get_closest_metadata(&self, point: cgmath::Point3<f64>) -> Material {
+    // the distance rays step by, every object's estimate shortened by its own factor. it's still never
+    // more than the real distance, and objects without a Lipschitz in them keep their full steps
+    fn march_distance(&self, point: cgmath::Point3<f64>, time: f64) -> f64 {
         self.objects
             .iter()
-            .map(|x| x.get_metadata(point, &self.materials))
+            .zip(&self.step_scales)
+            .map(|(x, scale)| x.estimate_distance(point, time) / scale)
+            .reduce(f64::min)
+            .unwrap_or(0.0)
+    }
+
+    fn get_closest_metadata(&self, point: cgmath::Point3<f64>, time: f64) -> Material {
+        self.objects
+            .iter()
+            .map(|x| x.get_metadata(point, time, self))
             .reduce(|acc, x| if x.0 < acc.0 { x } else { acc })
             .map(|(_, mat)| mat)
-            .unwrap_or(BLACK_MATERIAL)
+            .unwrap_or_else(|| BLACK_MATERIAL.clone())
+    }
+
+    // the number of the top level object and of the material closest to point
+    fn closest_ids(&self, point: cgmath::Point3<f64>, time: f64) -> (Option<u32>, Option<u32>) {
+        self.objects
+            .iter()
+            .zip(0..)
+            .map(|(o, i)| (i, o.closest_material(point, time)))
+            .min_by(|a, b| a.1 .0.total_cmp(&b.1 .0))
+            .map_or((None, None), |(i, (_, material))| {
+                (
+                    Some(i),
+                    material.and_then(|m| self.material_ids.get(m)).copied(),
+                )
+            })
     }
 
-    fn get_distance_gradient(&self, point: cgmath::Point3<f64>) -> cgmath::Vector3<f64> {
-        let x_neg = self.estimate_distance(point + cgmath::vec3(-0.005, 0.0, 0.0));
-        let x_pos = self.estimate_distance(point + cgmath::vec3(0.005, 0.0, 0.0));
-        let y_neg = self.estimate_distance(point + cgmath::vec3(0.0, -0.005, 0.0));
-        let y_pos = self.estimate_distance(point + cgmath::vec3(0.0, 0.005, 0.0));
-        let z_neg = self.estimate_distance(point + cgmath::vec3(0.0, 0.0, -0.005));
-        let z_pos = self.estimate_distance(point + cgmath::vec3(0.0, 0.0, 0.005));
+    fn get_distance_gradient(&self, point: cgmath::Point3<f64>, time: f64) -> cgmath::Vector3<f64> {
+        let h = self.march.gradient_offset;
+        let x_neg = self.estimate_distance(point + cgmath::vec3(-h, 0.0, 0.0), time);
+        let x_pos = self.estimate_distance(point + cgmath::vec3(h, 0.0, 0.0), time);
+        let y_neg = self.estimate_distance(point + cgmath::vec3(0.0, -h, 0.0), time);
+        let y_pos = self.estimate_distance(point + cgmath::vec3(0.0, h, 0.0), time);
+        let z_neg = self.estimate_distance(point + cgmath::vec3(0.0, 0.0, -h), time);
+        let z_pos = self.estimate_distance(point + cgmath::vec3(0.0, 0.0, h), time);
         cgmath::vec3(x_pos - x_neg, y_pos - y_neg, z_pos - z_neg)
     }
 }
@@ -264,265 +1114,649 @@ struct HitResult {
     position: cgmath::Point3<f64>,
     previous_position: cgmath::Point3<f64>,
     hit_anything: bool,
+    steps: u32,
 }
 
+// how far off the surface a ray that passes through it starts, so it doesn't hit the same spot again
+static SURFACE_OFFSET: f64 = 0.001;
+
+// when inside is set the ray marches through the inside of objects, looking for the way out.
+// footprint is how wide the pixel the ray came from is for march.pixel_epsilon, only rays straight from the camera
+// have one since where a bounce starts has nothing to do with how far from the camera it is
 fn cast_ray(
     from: cgmath::Point3<f64>,
     direction: cgmath::Vector3<f64>,
+    footprint: Footprint,
     world: &World,
+    inside: bool,
+    time: f64,
 ) -> HitResult {
+    let march = &world.march;
+    let distance = |point| {
+        let distance = world.march_distance(point, time);
+        if inside {
+            -distance
+        } else {
+            distance
+        }
+    };
     let mut position = from;
     let mut prev_pos = from;
-    for _ in 0..1000 {
-        let current_distance = world.estimate_distance(position);
-        if current_distance < 0.0001 {
+    let mut travelled = 0.0;
+    // over-relaxed sphere tracing from enhanced sphere tracing by keinert et al, steps are stretched by
+    // relaxation for as long as the spheres around each point keep overlapping
+    let mut relaxation = march.relaxation;
+    let mut prev_distance = 0.0;
+    let mut step = 0.0;
+    for steps in 0..march.max_steps {
+        let current_distance = distance(position);
+        if relaxation > 1.0 && current_distance.abs() + prev_distance < step {
+            // there's a gap between the spheres that a surface could be hiding in,
+            // so go back and carry on without stretching the steps
+            position = prev_pos;
+            travelled -= step;
+            relaxation = 1.0;
+            step = 0.0;
+            continue;
+        }
+        let epsilon = march.epsilon_at(footprint, travelled);
+        if current_distance < epsilon {
+            // previous_position stays where it is, it's the last point that was far enough away from
+            // the surface for rays to start from
+            let mut hit = position;
+            if march.refine_steps > 0 {
+                // usually the ray stopped just in front of the surface, so it has to be found behind it first
+                // by looking a bit further ahead. rays that only graze it never get past, those stay where they are
+                let (mut outside, mut past) = if current_distance > 0.0 {
+                    (position, None)
+                } else {
+                    (prev_pos, Some(position))
+                };
+                let mut ahead = 2.0 * epsilon;
+                while past.is_none() && ahead <= 16.0 * epsilon {
+                    let probe = position + direction * ahead;
+                    if distance(probe) > 0.0 {
+                        outside = probe;
+                        ahead *= 2.0;
+                    } else {
+                        past = Some(probe);
+                    }
+                }
+                if let Some(mut past) = past {
+                    for _ in 0..march.refine_steps {
+                        let middle = outside.midpoint(past);
+                        if distance(middle) > 0.0 {
+                            outside = middle;
+                        } else {
+                            past = middle;
+                        }
+                    }
+                    hit = outside.midpoint(past);
+                }
+            }
             return HitResult {
-                position,
+                position: hit,
                 previous_position: prev_pos,
                 hit_anything: true,
+                steps,
             };
         }
-        if current_distance > 10000.0 {
+        if current_distance > march.far {
             return HitResult {
                 position,
                 previous_position: prev_pos,
                 hit_anything: false,
+                steps,
             };
         }
         prev_pos = position;
-        position += direction * current_distance;
+        prev_distance = current_distance;
+        step = current_distance * relaxation;
+        position += direction * step;
+        travelled += step;
     }
     HitResult {
         position,
         previous_position: prev_pos,
         hit_anything: false,
+        steps: march.max_steps,
     }
 }
 
-fn distribution_ggx(
-    normal: cgmath::Vector3<f64>,
-    halfway: cgmath::Vector3<f64>,
-    roughness: f64,
-) -> f64 {
-    let roughness2 = roughness.powi(4);
-    let ndot = normal.dot(halfway).max(0.0);
-    let denom = (ndot * ndot) * (roughness2 - 1.0) + 1.0;
-
-    roughness2 / (std::f64::consts::PI * denom * denom)
+// the stuff inside an object that a ray is currently travelling through
+struct Medium {
+    absorption: cgmath::Vector3<f64>,
+    scattering: cgmath::Vector3<f64>,
+    anisotropy: f64,
 }
 
-//returns the smallest and largest possible values for a given roughness
-fn distribution_ggx_limits(roughness: f64) -> (f64, f64) {
-    let roughness2 = roughness.powi(4);
-
-    (roughness2, roughness2 / (roughness2 * roughness2))
-}
-
-fn inverse_distribution_ggx(roughness: f64, value: f64) -> f64 {
-    let roughness2 = roughness.powi(4);
-
-    let denom = roughness2 * roughness2 * value - 2.0 * value * roughness2 + value;
+impl Medium {
+    fn from_material(material: &Material) -> Self {
+        Self {
+            absorption: material.absorption.map(|x| x.max(0.0)),
+            scattering: material.scattering.map(|x| x.max(0.0)),
+            anisotropy: material.anisotropy,
+        }
+    }
 
-    let num1 = value - roughness2 * value;
-    let num2 = ((roughness2 - 1.0) * (roughness2 - 1.0) * roughness2 * value).sqrt();
+    // free flight sampling, either finds a spot before max_distance where the ray scatters or lets it through.
+    // the distance is sampled using a random color channel, so the weight uses the pdf averaged over all of them
+    fn sample_distance<T: rand::Rng>(
+        &self,
+        max_distance: f64,
+        rand: &mut T,
+    ) -> (Option<f64>, cgmath::Vector3<f64>) {
+        if self.scattering.x + self.scattering.y + self.scattering.z <= 0.0 {
+            // plain beer-lambert
+            return (None, (-self.absorption * max_distance).map(f64::exp));
+        }
+        let extinction = self.absorption + self.scattering;
+        let sigma = extinction[rand.gen_range(0..3)];
+        let distance = if sigma > 0.0 {
+            -(1.0 - rand.gen::<f64>()).ln() / sigma
+        } else {
+            f64::INFINITY
+        };
 
-    let ans1 = (num1 - num2) / denom;
-    let ans2 = (num1 + num2) / denom;
+        if distance < max_distance {
+            let transmittance = (-extinction * distance).map(f64::exp);
+            let pdf = average(extinction.mul_element_wise(transmittance));
+            (
+                Some(distance),
+                self.scattering.mul_element_wise(transmittance) / pdf,
+            )
+        } else {
+            let transmittance = (-extinction * max_distance).map(f64::exp);
+            (None, transmittance / average(transmittance))
+        }
+    }
+}
 
-    if ans1 < 0.0 {
-        ans2
+fn sample_henyey_greenstein<T: rand::Rng>(
+    direction: cgmath::Vector3<f64>,
+    anisotropy: f64,
+    rand: &mut T,
+) -> cgmath::Vector3<f64> {
+    let u1: f64 = rand.gen();
+    let u2: f64 = rand.gen();
+    let g = anisotropy;
+    let cos_theta = if g.abs() < 0.001 {
+        1.0 - 2.0 * u1
     } else {
-        ans1
-    }
+        let sq = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+        ((1.0 + g * g - sq * sq) / (2.0 * g)).clamp(-1.0, 1.0)
+    };
+    let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+    let (z, x) = (2.0 * std::f64::consts::PI * u2).sin_cos();
+    // the phase function is sampled exactly, so there is no weight to apply
+    cgmath::Basis3::between_vectors(LOCAL_NORMAL, direction)
+        .rotate_vector(cgmath::vec3(x * sin_theta, cos_theta, z * sin_theta))
+        .normalize()
 }
 
-fn geometry_schlick_ggx(normal_dot_dir: f64, mapped_roughness: f64) -> f64 {
-    normal_dot_dir / (normal_dot_dir * (1.0 - mapped_roughness) + mapped_roughness)
+// paths that can't contribute much anymore get killed off, the survivors make up for them
+fn russian_roulette<T: rand::Rng>(
+    throughput: &mut cgmath::Vector3<f64>,
+    depth: u32,
+    world: &World,
+    rand: &mut T,
+) -> bool {
+    if depth < world.russian_roulette_depth {
+        return true;
+    }
+    let survival_chance = throughput
+        .x
+        .max(throughput.y)
+        .max(throughput.z)
+        .clamp(0.05, 1.0);
+    if rand.gen::<f64>() >= survival_chance {
+        return false;
+    }
+    *throughput /= survival_chance;
+    true
 }
 
-fn geometry_smith(
+// bump mapping only tilts the normal used for shading, the surface stays where it is
+fn shading_normal(
     normal: cgmath::Vector3<f64>,
-    view: cgmath::Vector3<f64>,
-    light: cgmath::Vector3<f64>,
-    roughness: f64,
-) -> f64 {
-    let r = roughness + 1.0;
-    let k = (r * r) / 8.0;
-    geometry_schlick_ggx(normal.dot(view).max(0.0), k)
-        * geometry_schlick_ggx(normal.dot(light).max(0.0), k)
-}
-
-fn fresnel_schlick(cos_theta: f64, f0: cgmath::Vector3<f64>) -> cgmath::Vector3<f64> {
-    f0 + f0.map(|v| 1.0 - v) * (1.0 - cos_theta).clamp(0.0, 1.0).powi(5)
-}
-
-fn select_direction<T: rand::Rng>(
-    view_dir: cgmath::Vector3<f64>,
-    roughness: f64,
-    metalness: f64,
-    mut rand: T,
-) -> (cgmath::Vector3<f64>, cgmath::Vector3<f64>, f64) {
-    if rand_distr::Bernoulli::new(1.0 - metalness)
-        .unwrap()
-        .sample(&mut rand)
-    {
-        // not metallic enough for the complex method!
-        let dist = rand_distr::UnitSphere;
-        let ray_dir: [f64; 3] = dist.sample(&mut rand);
-        let ray_dir = cgmath::vec3(ray_dir[0], ray_dir[1].abs(), ray_dir[2]);
-        let halfway = (ray_dir + view_dir).normalize();
-        return (
-            ray_dir,
-            halfway,
-            distribution_ggx(cgmath::vec3(0.0, 1.0, 0.0), halfway, roughness),
-        );
+    metadata: &Material,
+    direction: cgmath::Vector3<f64>,
+) -> cgmath::Vector3<f64> {
+    let shading_normal =
+        (normal - (metadata.bump - normal * metadata.bump.dot(normal))).normalize();
+    if shading_normal.dot(-direction) > 0.0 {
+        shading_normal
+    } else {
+        normal
     }
-    let (min_ndf, max_ndf) = distribution_ggx_limits(roughness);
+}
 
-    if max_ndf.is_nan() {
-        //roughness is 0, the halfway vector must be lined up exactly
-        let normal = cgmath::vec3(0.0, 1.0, 0.0);
-        return (
-            (-view_dir) - (normal.dot(-view_dir) * 2.0 * normal),
-            normal,
-            1.0,
-        );
-    }
+// the whole path happens at time, so everything it sees is where it was at that moment
+pub fn render_ray(
+    from: cgmath::Point3<f64>,
+    direction: cgmath::Vector3<f64>,
+    footprint: Footprint,
+    world: &World,
+    time: f64,
+) -> cgmath::Vector3<f64> {
+    let mut rand = rand::thread_rng();
+    let sky_color = world.animation.sky_color_at(world.sky_color, time);
 
-    let num = rand::distributions::Uniform::new_inclusive(0.0, 1.0).sample(&mut rand);
-    let num = num * num;
-    let num = num * (max_ndf - min_ndf) + min_ndf;
+    let mut color = BLACK;
+    let mut throughput = cgmath::vec3(1.0, 1.0, 1.0);
+    let mut from = from;
+    let mut direction = direction;
+    // set while the ray is inside of an object
+    let mut medium: Option<Medium> = None;
 
-    let cos_angle = inverse_distribution_ggx(roughness, num);
+    for depth in 0..=world.max_ray_depth {
+        let footprint = if depth == 0 {
+            footprint
+        } else {
+            Footprint::default()
+        };
+        let ray = cast_ray(from, direction, footprint, world, medium.is_some(), time);
 
-    if cos_angle.is_nan() {
-        let dist = rand_distr::UnitSphere;
-        // roughness is exactly 1, which means the direction must not be biased
-        let ray_dir: [f64; 3] = dist.sample(&mut rand);
-        let ray_dir = cgmath::vec3(ray_dir[0], ray_dir[1].abs(), ray_dir[2]);
-        return (ray_dir, (ray_dir + view_dir).normalize(), 1.0);
-    }
+        // the stretch the ray just marched can scatter it, either inside an object or in the fog outside
+        let segment_length = from.distance(ray.position);
+        let scattered = match &medium {
+            Some(medium) => {
+                let (distance, weight) = medium.sample_distance(segment_length, &mut rand);
+                throughput.mul_assign_element_wise(weight);
+                distance.map(|d| (d, medium.anisotropy))
+            }
+            None => {
+                // a ray that gets away has fog all the way to the sky, not just as far as the march went
+                let fog_length = if ray.hit_anything {
+                    segment_length
+                } else {
+                    f64::INFINITY
+                };
+                world
+                    .atmosphere
+                    .sample_scatter(from, direction, fog_length, &mut rand)
+                    .map(|d| {
+                        throughput.mul_assign_element_wise(world.atmosphere.color);
+                        (d, world.atmosphere.anisotropy)
+                    })
+            }
+        };
+        if let Some((distance, anisotropy)) = scattered {
+            if depth == world.max_ray_depth
+                || !russian_roulette(&mut throughput, depth, world, &mut rand)
+            {
+                break;
+            }
+            from += direction * distance;
+            direction = sample_henyey_greenstein(direction, anisotropy, &mut rand);
+            continue;
+        }
 
-    let theta =
-        rand::distributions::Uniform::new(0.0, 2.0 * std::f64::consts::PI).sample(&mut rand);
+        if !ray.hit_anything {
+            color += throughput.mul_element_wise(sky_color);
+            break;
+        }
+        let metadata = world.get_closest_metadata(ray.position, time);
+        color += throughput.mul_element_wise(metadata.emitance);
+        if depth == world.max_ray_depth {
+            break;
+        }
 
-    let sin_angle = (1.0 - cos_angle * cos_angle).sqrt();
+        // the gradient always points out of the object, but the shading wants it facing the ray
+        let inside = medium.is_some();
+        let normal = world.get_distance_gradient(ray.position, time).normalize();
+        let normal = if inside { -normal } else { normal };
+        let shading_normal = shading_normal(normal, &metadata, direction);
+        let frame = Frame::new(shading_normal, metadata.tangent);
+        let eta = if inside {
+            metadata.ior
+        } else {
+            1.0 / metadata.ior
+        };
 
-    let (x, z) = theta.sin_cos();
-    let (x, z) = (x * sin_angle, z * sin_angle);
+        let sample = match sample_bsdf(frame.to_local(-direction), &metadata, eta, &mut rand) {
+            Some(sample) => sample,
+            None => break,
+        };
+        throughput.mul_assign_element_wise(sample.weight);
+        if !russian_roulette(&mut throughput, depth, world, &mut rand) {
+            break;
+        }
 
-    let halfway = cgmath::vec3(x, cos_angle, z).normalize();
+        direction = frame.to_world(sample.direction).normalize();
+        // with a tilted normal a sample can end up on the wrong side of the real surface, those can't go anywhere
+        if (sample.direction.y < 0.0) != (direction.dot(normal) < 0.0) {
+            break;
+        }
+        if sample.direction.y < 0.0 {
+            medium = if inside {
+                None
+            } else {
+                Some(Medium::from_material(&metadata))
+            };
+            from = ray.position - normal * SURFACE_OFFSET;
+        } else {
+            from = ray.previous_position;
+        }
+    }
+    color
+}
 
-    (
-        (-view_dir) - (halfway.dot(-view_dir) * 2.0 * halfway),
-        halfway,
-        1.0,
-    )
+// what the first thing a ray hits looks like without any lighting, for the aovs
+pub fn first_hit(
+    from: cgmath::Point3<f64>,
+    direction: cgmath::Vector3<f64>,
+    footprint: Footprint,
+    world: &World,
+    time: f64,
+) -> Aovs {
+    let ray = cast_ray(from, direction, footprint, world, false, time);
+    let mut aovs = Aovs {
+        steps: ray.steps as f64,
+        ..Aovs::default()
+    };
+    if !ray.hit_anything {
+        aovs.emission = world.animation.sky_color_at(world.sky_color, time);
+        return aovs;
+    }
+    let metadata = world.get_closest_metadata(ray.position, time);
+    let normal = world.get_distance_gradient(ray.position, time).normalize();
+    let (object_id, material_id) = world.closest_ids(ray.position, time);
+    Aovs {
+        depth: from.distance(ray.position),
+        normal: shading_normal(normal, &metadata, direction),
+        albedo: metadata.color,
+        emission: metadata.emitance,
+        material_id,
+        object_id,
+        ..aovs
+    }
 }
 
-pub fn render_ray(
+// where a ray starts, the way it goes and how wide its pixel is
+type Ray = (cgmath::Point3<f64>, cgmath::Vector3<f64>, Footprint);
+
+// what the debug modes show for a ray, the colors come out ready to be shown as they are
+pub fn debug_ray(
+    mode: DebugMode,
+    slice: Slice,
     from: cgmath::Point3<f64>,
     direction: cgmath::Vector3<f64>,
+    footprint: Footprint,
     world: &World,
-    depth: u32,
+    time: f64,
 ) -> cgmath::Vector3<f64> {
-    let ray = cast_ray(from, direction, world);
-    if ray.hit_anything {
-        let metadata = world.get_closest_metadata(ray.position);
-        if depth == world.max_ray_depth {
-            return metadata.emitance;
+    let ray = cast_ray(from, direction, footprint, world, false, time);
+    let normal = || world.get_distance_gradient(ray.position, time).normalize();
+    // the scene lit from the camera, for the modes that draw over it
+    let grey = || {
+        let light = if ray.hit_anything {
+            0.2 + 0.6 * normal().dot(-direction).max(0.0)
+        } else {
+            0.05
+        };
+        cgmath::vec3(light, light, light)
+    };
+    match mode {
+        // on a log scale, most rays only take a few steps
+        DebugMode::Steps => {
+            heatmap((ray.steps as f64 + 1.0).ln() / (world.march.max_steps as f64 + 1.0).ln())
         }
-        //send a new ray, get diffuse and specular weight, do math
-        let normal = world.get_distance_gradient(ray.position).normalize();
-        let rotation = cgmath::Basis3::between_vectors(cgmath::vec3(0.0, 1.0, 0.0), normal);
-
-        let rand = rand::thread_rng();
-
-        // experiment: bias the ray selection so directions more likely so influence the final color are more likely to be chosen
-        let (ray_dir, halfway, ndf) = select_direction(
-            rotation.invert().rotate_vector(-direction),
-            metadata.roughness,
-            metadata.metalness,
-            rand,
-        );
-        let (ray_dir, halfway) = (
-            rotation.rotate_vector(ray_dir).normalize(),
-            rotation.rotate_vector(halfway),
-        );
-
-        let ray_color = render_ray(ray.previous_position, ray_dir, world, depth + 1);
-        let f0 = cgmath::vec3(0.04, 0.04, 0.04);
-        let f0 = f0.lerp(metadata.color, metadata.metalness);
-        let f = fresnel_schlick(normal.dot(halfway).max(0.0), f0);
-        let g = geometry_smith(normal, -direction, ray_dir, metadata.roughness);
-        let specular = (g * f * ndf)
-            / (4.0 * normal.dot(-direction).max(0.0) * normal.dot(ray_dir).max(0.0) + 0.000001);
-        let k_d = f.map(|x| 1.0 - x);
-        let k_d = k_d * (1.0 - metadata.metalness);
-        (k_d.mul_element_wise(metadata.color) / std::f64::consts::PI + specular)
-            .mul_element_wise(ray_color)
-            * normal.dot(ray_dir).max(0.0)
-            * 10.0
-            + metadata.emitance
-    } else {
-        BLACK
+        DebugMode::HitMask if ray.hit_anything => cgmath::vec3(1.0, 1.0, 1.0),
+        DebugMode::HitMask => BLACK,
+        DebugMode::Normals if ray.hit_anything => normal() * 0.5 + cgmath::vec3(0.5, 0.5, 0.5),
+        DebugMode::Normals => BLACK,
+        DebugMode::Slice => match slice.intersect(from, direction) {
+            Some(t) if !ray.hit_anything || t < from.distance(ray.position) => {
+                distance_color(world.estimate_distance(from + direction * t, time))
+            }
+            _ => grey(),
+        },
+        DebugMode::StepLimit if ray.steps >= world.march.max_steps => cgmath::vec3(1.0, 0.0, 1.0),
+        DebugMode::StepLimit => grey(),
     }
 }
 
-pub fn render_pixel(
+// a ray for one sample of a pixel and the moment it happens at,
+// there's no ray for the parts of the picture the camera doesn't cover
+fn camera_ray<T: rand::Rng>(
     (width, height): (u32, u32),
     pixel_idx: u32,
     world: &World,
-) -> (f64, f64, f64, f64) {
+    rand: &mut T,
+) -> (f64, Option<Ray>) {
     let pixel_pos = (pixel_idx % width, pixel_idx / width);
     let pixel_pos = (
         (pixel_pos.0 as f64 / width as f64 - 0.5) * 2.0,
-        (pixel_pos.1 as f64 / height as f64 - 0.5) * 2.0,
+        (pixel_pos.1 as f64 / height as f64 - 0.5) * 2.0 * height as f64 / width as f64,
     );
 
-    let color = render_ray(
-        cgmath::point3(0.0, 0.0, 0.0),
-        cgmath::vec3(pixel_pos.0, pixel_pos.1, 1.0).normalize(),
-        world,
-        0,
-    );
+    let time = world.sample_time(rand);
+    let mut camera = world.animation.camera_at(world.camera, time);
+    if let Some(distance) = world.autofocus_distance {
+        camera.focal_distance = distance;
+    }
+    let footprint = camera.footprint(width);
+    let ray = camera.ray(pixel_pos.0, pixel_pos.1, rand);
+    (
+        time,
+        ray.map(|(from, direction)| (from, direction, footprint)),
+    )
+}
+
+pub fn render_pixel_aovs((width, height): (u32, u32), pixel_idx: u32, world: &World) -> Aovs {
+    match camera_ray((width, height), pixel_idx, world, &mut rand::thread_rng()) {
+        (time, Some((from, direction, footprint))) => {
+            first_hit(from, direction, footprint, world, time)
+        }
+        (_, None) => Aovs::default(),
+    }
+}
+
+pub fn render_pixel_debug(
+    (width, height): (u32, u32),
+    pixel_idx: u32,
+    world: &World,
+    mode: DebugMode,
+    slice: Slice,
+) -> cgmath::Vector3<f64> {
+    match camera_ray((width, height), pixel_idx, world, &mut rand::thread_rng()) {
+        (time, Some((from, direction, footprint))) => {
+            debug_ray(mode, slice, from, direction, footprint, world, time)
+        }
+        (_, None) => BLACK,
+    }
+}
+
+pub fn render_pixel(
+    (width, height): (u32, u32),
+    pixel_idx: u32,
+    world: &World,
+) -> (f64, f64, f64, f64) {
+    let color = match camera_ray((width, height), pixel_idx, world, &mut rand::thread_rng()) {
+        (time, Some((from, direction, footprint))) => {
+            render_ray(from, direction, footprint, world, time)
+        }
+        (_, None) => BLACK,
+    };
     //color.div_assign_element_wise(color.map(|x| x + 1.0));
     (color.x, color.y, color.z, 1.0)
 }
 
-pub fn render_to_buffer(buffer: Arc<Mutex<Vec<u8>>>, (width, height): (u32, u32), world: &World) {
-    let (mut sender, mut reciever) = futures::channel::mpsc::unbounded::<(usize, [f64; 4])>();
-    let reciever = spawn(move || {
-        let mut ray_count = vec![0usize; (width * height) as usize];
-        let mut actual_buffer = vec![0f64; (width * height * 4) as usize];
-        'outer: loop {
-            if let Ok(mut lock) = buffer.lock() {
+// how the accumulated picture gets shown, changing it redraws what's there without restarting the render
+#[derive(Clone, Copy, PartialEq)]
+pub struct Display {
+    pub tone_mapping: ToneMapping,
+    // shows the picture denoised, which gets redone every time another sample per pixel has come in
+    pub denoise: bool,
+    // these do restart the render, and turn off tone mapping and denoising while they're on
+    pub debug: Option<DebugMode>,
+    pub slice: Slice,
+}
+
+impl Display {
+    // what gets rendered, samples of something else are thrown away
+    fn view(&self) -> (Option<DebugMode>, Slice) {
+        (self.debug, self.slice)
+    }
+}
+
+pub fn render_to_buffer(
+    buffer: Arc<Mutex<Vec<u8>>>,
+    display: Arc<Mutex<Display>>,
+    (width, height): (u32, u32),
+    world: &World,
+) {
+    let pixel_count = (width * height) as usize;
+    let (mut sender, mut reciever) =
+        futures::channel::mpsc::unbounded::<(usize, [f64; 4], (Option<DebugMode>, Slice))>();
+    let display_for_render = display.clone();
+    scope(|scope| {
+        let reciever = scope.spawn(move || {
+            let mut ray_count = vec![0usize; pixel_count];
+            let mut actual_buffer = vec![0f64; pixel_count * 4];
+            let mut current_display = *display.lock().unwrap();
+            // what the denoiser goes by, only worked out once it gets turned on
+            let mut features = None;
+            let mut samples_since_denoise = 0;
+            'outer: loop {
+                let new_display = *display.lock().unwrap();
+                let denoising = new_display.denoise && new_display.debug.is_none();
+                if new_display != current_display
+                    || (denoising && samples_since_denoise >= pixel_count)
+                {
+                    if new_display.view() != current_display.view() {
+                        ray_count.fill(0);
+                        actual_buffer.fill(0.0);
+                    }
+                    // the accumulated values are still hdr, so just redo the mapping for everything
+                    current_display = new_display;
+                    let colors: Vec<_> = (0..pixel_count)
+                        .map(|index| hdr_color(&actual_buffer, index))
+                        .collect();
+                    let colors = if denoising {
+                        samples_since_denoise = 0;
+                        let features =
+                            features.get_or_insert_with(|| aov::render(world, (width, height), 4));
+                        denoise(&colors, features, (width, height))
+                    } else {
+                        colors
+                    };
+                    let mut lock = buffer.lock().unwrap();
+                    for (index, color) in colors.into_iter().enumerate() {
+                        let alpha = actual_buffer[index * 4 + 3];
+                        write_pixel(lock.deref_mut(), index, color, alpha, &current_display);
+                    }
+                }
                 let r = reciever.try_next();
                 match r {
-                    Ok(Some((index, val))) => {
+                    Ok(Some((_, _, view))) if view != current_display.view() => {}
+                    Ok(Some((index, val, _))) => {
                         ray_count[index] += 1;
                         let ray_count = ray_count[index] as f64;
                         for (n, item) in val.iter().enumerate() {
                             let old_val = actual_buffer[index * 4 + n];
                             let new_val = (item + old_val * (ray_count - 1.0)) / ray_count;
                             actual_buffer[index * 4 + n] = new_val;
-                            let new_val = new_val / (new_val + 1.0);
-                            lock.deref_mut()[index * 4 + n] = (new_val * 255.0) as u8;
+                        }
+                        samples_since_denoise += 1;
+                        // a denoised picture only gets redrawn all at once
+                        if !denoising {
+                            write_pixel(
+                                buffer.lock().unwrap().deref_mut(),
+                                index,
+                                hdr_color(&actual_buffer, index),
+                                actual_buffer[index * 4 + 3],
+                                &current_display,
+                            );
                         }
                     }
                     Ok(None) => break 'outer,
                     Err(_) => continue,
                 }
             }
-        }
+        });
+        (0..)
+            .par_bridge()
+            .map(|p| p % (width * height))
+            .map(|pos| {
+                let view = display_for_render.lock().unwrap().view();
+                let (r, g, b, a) = match view {
+                    (None, _) => render_pixel((width, height), pos, world),
+                    (Some(mode), slice) => {
+                        let color = render_pixel_debug((width, height), pos, world, mode, slice);
+                        (color.x, color.y, color.z, 1.0)
+                    }
+                };
+                (pos as usize, [b, g, r, a], view)
+            })
+            .for_each(|a| sender.unbounded_send(a).unwrap());
+        sender.disconnect();
+        reciever.join().unwrap();
     });
-    (0..)
-        .into_iter()
-        .par_bridge()
-        .map(|p| p % (width * height))
-        .map(|pos| (pos, render_pixel((width, height), pos, world)))
-        .map(|(pos, (b, g, r, a))| (pos as usize, [r, g, b, a]))
-        .for_each(|a| sender.unbounded_send(a).unwrap());
-    sender.disconnect();
-    reciever.join().unwrap();
+}
+
+// buffers are stored as bgra
+fn hdr_color(hdr: &[f64], index: usize) -> cgmath::Vector3<f64> {
+    cgmath::vec3(hdr[index * 4 + 2], hdr[index * 4 + 1], hdr[index * 4])
+}
+
+fn write_pixel(
+    output: &mut [u8],
+    index: usize,
+    color: cgmath::Vector3<f64>,
+    alpha: f64,
+    display: &Display,
+) {
+    let mapped = match display.debug {
+        Some(_) => color.map(|c| c.clamp(0.0, 1.0)),
+        None => display.tone_mapping.map(color),
+    };
+    output[index * 4] = (mapped.z * 255.0).round() as u8;
+    output[index * 4 + 1] = (mapped.y * 255.0).round() as u8;
+    output[index * 4 + 2] = (mapped.x * 255.0).round() as u8;
+    output[index * 4 + 3] = (alpha.clamp(0.0, 1.0) * 255.0).round() as u8;
+}
+
+#[cfg(test)]
+mod tests {
+    use rand_distr::Distribution;
+
+    use super::*;
+
+    // every ray that hits one of the white furnace spheres (diffuse, ggx metal and glass)
+    // should come back with the sky's color on average
+    #[test]
+    fn white_furnace() {
+        let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR")).join("furnace.ron");
+        let mut world: World = crate::scene_file::load(&path).unwrap();
+        world.prepare(&path).unwrap();
+        let mut rand = rand::thread_rng();
+        for center in [
+            cgmath::point3(-1.0, 0.0, 3.0),
+            cgmath::point3(0.0, 0.0, 3.0),
+            cgmath::point3(1.0, 0.0, 3.0),
+        ] {
+            let samples = 20000;
+            let mut sum = BLACK;
+            for _ in 0..samples {
+                // anywhere on the sphere that faces the camera, a bit in from the edge so every ray hits
+                let [x, y]: [f64; 2] = rand_distr::UnitDisc.sample(&mut rand);
+                let target = center + cgmath::vec3(x, y, 0.0) * 0.4;
+                let from = cgmath::point3(0.0, 0.0, 0.0);
+                let direction = (target - from).normalize();
+                sum += render_ray(from, direction, Footprint::default(), &world, 0.0);
+            }
+            // rough metal loses a little to the bounces ggx doesn't model, but nothing gets brighter than the sky
+            let mean = sum / samples as f64;
+            for channel in 0..3 {
+                assert!(
+                    (mean[channel] - world.sky_color[channel]).abs() < 0.03,
+                    "the sphere at {:?} came out {:?}",
+                    center,
+                    mean
+                );
+            }
+        }
+    }
 }
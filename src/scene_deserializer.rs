@@ -0,0 +1,336 @@
+use serde::de::{self, DeserializeSeed, Visitor};
+
+use crate::params::Params;
+
+// wraps another deserializer and lets a string with an expression stand in wherever it gets asked for a float
+// or a vector. everything else goes through untouched, so the rest of the scene doesn't know about any of this
+pub struct Deserializer<'p, D> {
+    inner: D,
+    params: &'p Params,
+}
+
+impl<'p, D> Deserializer<'p, D> {
+    pub fn new(inner: D, params: &'p Params) -> Self {
+        Self { inner, params }
+    }
+}
+
+// the same wrapper for visitors and all the access types, so what they hand out gets wrapped too
+struct Wrap<'p, T> {
+    inner: T,
+    params: &'p Params,
+}
+
+impl<'p, T> Wrap<'p, T> {
+    fn new(inner: T, params: &'p Params) -> Self {
+        Self { inner, params }
+    }
+}
+
+macro_rules! forward {
+    ($($method:ident($($arg:ident: $ty:ty),*)),* $(,)?) => {
+        $(
+            fn $method<V: Visitor<'de>>(self, $($arg: $ty,)* visitor: V) -> Result<V::Value, D::Error> {
+                self.inner.$method($($arg,)* Wrap::new(visitor, self.params))
+            }
+        )*
+    };
+}
+
+impl<'de, 'p, D: de::Deserializer<'de>> de::Deserializer<'de> for Deserializer<'p, D> {
+    type Error = D::Error;
+
+    forward!(
+        deserialize_any(),
+        deserialize_bool(),
+        deserialize_i8(),
+        deserialize_i16(),
+        deserialize_i32(),
+        deserialize_i64(),
+        deserialize_u8(),
+        deserialize_u16(),
+        deserialize_u32(),
+        deserialize_u64(),
+        deserialize_char(),
+        deserialize_str(),
+        deserialize_string(),
+        deserialize_bytes(),
+        deserialize_byte_buf(),
+        deserialize_option(),
+        deserialize_unit(),
+        deserialize_unit_struct(name: &'static str),
+        deserialize_newtype_struct(name: &'static str),
+        deserialize_seq(),
+        deserialize_tuple(len: usize),
+        deserialize_tuple_struct(name: &'static str, len: usize),
+        deserialize_map(),
+        deserialize_enum(name: &'static str, variants: &'static [&'static str]),
+        deserialize_identifier(),
+        deserialize_ignored_any(),
+    );
+
+    fn deserialize_f32<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.deserialize_f64(visitor)
+    }
+
+    fn deserialize_f64<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, D::Error> {
+        self.inner.deserialize_any(ScalarVisitor {
+            inner: visitor,
+            params: self.params,
+        })
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(
+        self,
+        name: &'static str,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, D::Error> {
+        if matches!(name, "Vector3" | "Point3") && fields == ["x", "y", "z"] {
+            return self.inner.deserialize_any(VectorVisitor {
+                inner: visitor,
+                params: self.params,
+            });
+        }
+        self.inner
+            .deserialize_struct(name, fields, Wrap::new(visitor, self.params))
+    }
+
+    fn is_human_readable(&self) -> bool {
+        self.inner.is_human_readable()
+    }
+}
+
+struct ScalarVisitor<'p, V> {
+    inner: V,
+    params: &'p Params,
+}
+
+impl<'de, 'p, V: Visitor<'de>> Visitor<'de> for ScalarVisitor<'p, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a number or an expression")
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<V::Value, E> {
+        self.inner.visit_f64(v as f64)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<V::Value, E> {
+        self.inner.visit_f64(v as f64)
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<V::Value, E> {
+        self.inner.visit_f64(v)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<V::Value, E> {
+        let value = self
+            .params
+            .evaluate(v)
+            .and_then(|value| value.scalar().map_err(|e| format!("in \"{}\": {}", v, e)))
+            .map_err(E::custom)?;
+        self.inner.visit_f64(value)
+    }
+}
+
+struct VectorVisitor<'p, V> {
+    inner: V,
+    params: &'p Params,
+}
+
+impl<'de, 'p, V: Visitor<'de>> Visitor<'de> for VectorVisitor<'p, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "a vector or an expression")
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        self.inner.visit_map(Wrap::new(map, self.params))
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<V::Value, E> {
+        let value = self
+            .params
+            .evaluate(v)
+            .and_then(|value| value.vector().map_err(|e| format!("in \"{}\": {}", v, e)))
+            .map_err(E::custom)?;
+        self.inner.visit_map(de::value::MapDeserializer::new(
+            [("x", value.x), ("y", value.y), ("z", value.z)].into_iter(),
+        ))
+    }
+}
+
+impl<'de, 'p, V: Visitor<'de>> Visitor<'de> for Wrap<'p, V> {
+    type Value = V::Value;
+
+    fn expecting(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        self.inner.expecting(f)
+    }
+
+    fn visit_bool<E: de::Error>(self, v: bool) -> Result<V::Value, E> {
+        self.inner.visit_bool(v)
+    }
+
+    fn visit_i64<E: de::Error>(self, v: i64) -> Result<V::Value, E> {
+        self.inner.visit_i64(v)
+    }
+
+    fn visit_i128<E: de::Error>(self, v: i128) -> Result<V::Value, E> {
+        self.inner.visit_i128(v)
+    }
+
+    fn visit_u64<E: de::Error>(self, v: u64) -> Result<V::Value, E> {
+        self.inner.visit_u64(v)
+    }
+
+    fn visit_u128<E: de::Error>(self, v: u128) -> Result<V::Value, E> {
+        self.inner.visit_u128(v)
+    }
+
+    fn visit_f64<E: de::Error>(self, v: f64) -> Result<V::Value, E> {
+        self.inner.visit_f64(v)
+    }
+
+    fn visit_char<E: de::Error>(self, v: char) -> Result<V::Value, E> {
+        self.inner.visit_char(v)
+    }
+
+    fn visit_str<E: de::Error>(self, v: &str) -> Result<V::Value, E> {
+        self.inner.visit_str(v)
+    }
+
+    fn visit_borrowed_str<E: de::Error>(self, v: &'de str) -> Result<V::Value, E> {
+        self.inner.visit_borrowed_str(v)
+    }
+
+    fn visit_string<E: de::Error>(self, v: String) -> Result<V::Value, E> {
+        self.inner.visit_string(v)
+    }
+
+    fn visit_bytes<E: de::Error>(self, v: &[u8]) -> Result<V::Value, E> {
+        self.inner.visit_bytes(v)
+    }
+
+    fn visit_borrowed_bytes<E: de::Error>(self, v: &'de [u8]) -> Result<V::Value, E> {
+        self.inner.visit_borrowed_bytes(v)
+    }
+
+    fn visit_byte_buf<E: de::Error>(self, v: Vec<u8>) -> Result<V::Value, E> {
+        self.inner.visit_byte_buf(v)
+    }
+
+    fn visit_none<E: de::Error>(self) -> Result<V::Value, E> {
+        self.inner.visit_none()
+    }
+
+    fn visit_some<D: de::Deserializer<'de>>(self, d: D) -> Result<V::Value, D::Error> {
+        self.inner.visit_some(Deserializer::new(d, self.params))
+    }
+
+    fn visit_unit<E: de::Error>(self) -> Result<V::Value, E> {
+        self.inner.visit_unit()
+    }
+
+    fn visit_newtype_struct<D: de::Deserializer<'de>>(self, d: D) -> Result<V::Value, D::Error> {
+        self.inner
+            .visit_newtype_struct(Deserializer::new(d, self.params))
+    }
+
+    fn visit_seq<A: de::SeqAccess<'de>>(self, seq: A) -> Result<V::Value, A::Error> {
+        self.inner.visit_seq(Wrap::new(seq, self.params))
+    }
+
+    fn visit_map<A: de::MapAccess<'de>>(self, map: A) -> Result<V::Value, A::Error> {
+        self.inner.visit_map(Wrap::new(map, self.params))
+    }
+
+    fn visit_enum<A: de::EnumAccess<'de>>(self, data: A) -> Result<V::Value, A::Error> {
+        self.inner.visit_enum(Wrap::new(data, self.params))
+    }
+}
+
+impl<'de, 'p, S: DeserializeSeed<'de>> DeserializeSeed<'de> for Wrap<'p, S> {
+    type Value = S::Value;
+
+    fn deserialize<D: de::Deserializer<'de>>(self, d: D) -> Result<S::Value, D::Error> {
+        self.inner.deserialize(Deserializer::new(d, self.params))
+    }
+}
+
+impl<'de, 'p, A: de::SeqAccess<'de>> de::SeqAccess<'de> for Wrap<'p, A> {
+    type Error = A::Error;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(
+        &mut self,
+        seed: T,
+    ) -> Result<Option<T::Value>, A::Error> {
+        self.inner.next_element_seed(Wrap::new(seed, self.params))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, 'p, A: de::MapAccess<'de>> de::MapAccess<'de> for Wrap<'p, A> {
+    type Error = A::Error;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(
+        &mut self,
+        seed: K,
+    ) -> Result<Option<K::Value>, A::Error> {
+        self.inner.next_key_seed(Wrap::new(seed, self.params))
+    }
+
+    fn next_value_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<T::Value, A::Error> {
+        self.inner.next_value_seed(Wrap::new(seed, self.params))
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        self.inner.size_hint()
+    }
+}
+
+impl<'de, 'p, A: de::EnumAccess<'de>> de::EnumAccess<'de> for Wrap<'p, A> {
+    type Error = A::Error;
+    type Variant = Wrap<'p, A::Variant>;
+
+    fn variant_seed<T: DeserializeSeed<'de>>(
+        self,
+        seed: T,
+    ) -> Result<(T::Value, Self::Variant), A::Error> {
+        let params = self.params;
+        let (value, variant) = self.inner.variant_seed(Wrap::new(seed, params))?;
+        Ok((value, Wrap::new(variant, params)))
+    }
+}
+
+impl<'de, 'p, A: de::VariantAccess<'de>> de::VariantAccess<'de> for Wrap<'p, A> {
+    type Error = A::Error;
+
+    fn unit_variant(self) -> Result<(), A::Error> {
+        self.inner.unit_variant()
+    }
+
+    fn newtype_variant_seed<T: DeserializeSeed<'de>>(self, seed: T) -> Result<T::Value, A::Error> {
+        self.inner
+            .newtype_variant_seed(Wrap::new(seed, self.params))
+    }
+
+    fn tuple_variant<V: Visitor<'de>>(self, len: usize, visitor: V) -> Result<V::Value, A::Error> {
+        self.inner
+            .tuple_variant(len, Wrap::new(visitor, self.params))
+    }
+
+    fn struct_variant<V: Visitor<'de>>(
+        self,
+        fields: &'static [&'static str],
+        visitor: V,
+    ) -> Result<V::Value, A::Error> {
+        self.inner
+            .struct_variant(fields, Wrap::new(visitor, self.params))
+    }
+}
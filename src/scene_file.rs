@@ -0,0 +1,235 @@
+use std::{collections::HashMap, path::Path};
+
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::{
+    material::present,
+    migration::{Migration, Upgrade},
+    params::{ParamSource, Params},
+    scene_deserializer,
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Format {
+    Ron,
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl Format {
+    // anything that isn't clearly one of the others is read as ron, like scenes always were
+    pub fn from_path(path: &Path) -> Self {
+        match path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase())
+            .as_deref()
+        {
+            Some("json") => Self::Json,
+            Some("yaml" | "yml") => Self::Yaml,
+            Some("toml") => Self::Toml,
+            _ => Self::Ron,
+        }
+    }
+}
+
+// the params and the version have to be known before the rest of the file can be read,
+// so they get read on their own first
+#[derive(Deserialize, Default)]
+#[serde(default)]
+struct Header {
+    params: HashMap<String, ParamSource>,
+    #[serde(deserialize_with = "present::deserialize")]
+    version: Option<u32>,
+}
+
+fn with_header<'de, T, D, E>(
+    path: &Path,
+    header: Result<Header, E>,
+    deserializer: D,
+) -> Result<T, String>
+where
+    T: Deserialize<'de> + Upgrade,
+    D: serde::Deserializer<'de>,
+    D::Error: std::fmt::Display,
+    E: std::fmt::Display,
+{
+    let header = header.map_err(|e| e.to_string())?;
+    let params = Params::resolve(&header.params)?;
+    let migration = Migration::new(header.version)?;
+    let mut value = T::deserialize(scene_deserializer::Deserializer::new(deserializer, &params))
+        .map_err(|e| e.to_string())?;
+    value.upgrade(&migration);
+    for warning in migration.into_warnings() {
+        println!("warning: {}: {}", path.display(), warning);
+    }
+    Ok(value)
+}
+
+// reads a scene or an included file in whichever format its extension says, with the params block
+// worked out and filled in everywhere it's used and anything from an older version upgraded
+pub fn load<T: DeserializeOwned + Upgrade>(path: &Path) -> Result<T, String> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+
+    let result = match Format::from_path(path) {
+        Format::Ron => ron::Deserializer::from_str(&text)
+            .map_err(|e| e.to_string())
+            .and_then(|mut deserializer| {
+                let value = with_header(path, ron::de::from_str(&text), &mut deserializer)?;
+                deserializer.end().map_err(|e| e.to_string())?;
+                Ok(value)
+            }),
+        Format::Json => {
+            let mut deserializer = serde_json::Deserializer::from_str(&text);
+            with_header(path, serde_json::from_str(&text), &mut deserializer).and_then(|value| {
+                deserializer.end().map_err(|e| e.to_string())?;
+                Ok(value)
+            })
+        }
+        Format::Yaml => with_header(
+            path,
+            serde_yaml::from_str(&text),
+            serde_yaml::Deserializer::from_str(&text),
+        ),
+        // toml's own deserializer only knows enums without fields, so it gets read as json's Value which knows them all
+        Format::Toml => toml::from_str::<toml::Value>(&text)
+            .map_err(|e| e.to_string())
+            .and_then(|value| serde_json::to_value(value).map_err(|e| e.to_string()))
+            .and_then(|value| with_header(path, Header::deserialize(&value), value)),
+    };
+    result.map_err(|e| format!("failed to read {}: {}", path.display(), e))
+}
+
+pub fn save<T: Serialize>(value: &T, path: &Path) -> Result<(), String> {
+    let text = match Format::from_path(path) {
+        Format::Ron => ron::ser::to_string_pretty(value, ron::ser::PrettyConfig::default())
+            .map_err(|e| e.to_string()),
+        Format::Json => serde_json::to_string_pretty(value).map_err(|e| e.to_string()),
+        Format::Yaml => serde_yaml::to_string(value).map_err(|e| e.to_string()),
+        // the toml crate can only write arrays that mix tables with other values as broken toml,
+        // so it gets written by hand from json's Value, which also turns enum variants into plain tables
+        Format::Toml => serde_json::to_value(value)
+            .map_err(|e| e.to_string())
+            .and_then(|value| write_toml(&value)),
+    }
+    .map_err(|e| format!("failed to write {}: {}", path.display(), e))?;
+    std::fs::write(path, text).map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+// top level tables and arrays of tables get their own sections, everything below them is written inline
+fn write_toml(value: &serde_json::Value) -> Result<String, String> {
+    let table = value
+        .as_object()
+        .ok_or("only tables can be written as toml")?;
+    let is_table = |v: &serde_json::Value| v.is_object();
+    let is_table_array = |v: &serde_json::Value| {
+        v.as_array()
+            .is_some_and(|a| !a.is_empty() && a.iter().all(is_table))
+    };
+
+    let mut out = String::new();
+    for (key, value) in table {
+        if !value.is_null() && !is_table(value) && !is_table_array(value) {
+            out += &format!("{} = {}\n", toml_key(key), toml_inline(value)?);
+        }
+    }
+    for (key, value) in table {
+        let sections = if is_table(value) {
+            vec![(format!("[{}]", toml_key(key)), value)]
+        } else if is_table_array(value) {
+            let header = format!("[[{}]]", toml_key(key));
+            value
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|v| (header.clone(), v))
+                .collect()
+        } else {
+            continue;
+        };
+        for (header, section) in sections {
+            out += &format!("\n{}\n", header);
+            for (key, value) in section.as_object().unwrap() {
+                if !value.is_null() {
+                    out += &format!("{} = {}\n", toml_key(key), toml_inline(value)?);
+                }
+            }
+        }
+    }
+    Ok(out)
+}
+
+fn toml_key(key: &str) -> String {
+    if !key.is_empty()
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+    {
+        key.to_string()
+    } else {
+        serde_json::Value::from(key).to_string()
+    }
+}
+
+fn toml_inline(value: &serde_json::Value) -> Result<String, String> {
+    Ok(match value {
+        serde_json::Value::Null => {
+            return Err("toml has no way to write a missing value".to_string())
+        }
+        // json's escapes are all valid in toml strings too
+        serde_json::Value::Bool(_)
+        | serde_json::Value::Number(_)
+        | serde_json::Value::String(_) => value.to_string(),
+        serde_json::Value::Array(values) => format!(
+            "[{}]",
+            values
+                .iter()
+                .map(toml_inline)
+                .collect::<Result<Vec<_>, _>>()?
+                .join(", ")
+        ),
+        serde_json::Value::Object(map) => format!(
+            "{{ {} }}",
+            map.iter()
+                .filter(|(_, v)| !v.is_null())
+                .map(|(k, v)| Ok(format!("{} = {}", toml_key(k), toml_inline(v)?)))
+                .collect::<Result<Vec<_>, String>>()?
+                .join(", ")
+        ),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pixel_drawer::World;
+
+    // a scene converted to every other format and back to ron comes out the same as it went in
+    #[test]
+    fn round_trip() {
+        let root = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let directory = std::env::temp_dir().join(format!("round_trip_{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        for scene in ["shapes.ron", "blend_motion.ron"] {
+            let original: World = load(&root.join(scene)).unwrap();
+            let expected = serde_json::to_value(&original).unwrap();
+            for extension in ["json", "yaml", "toml"] {
+                let converted = directory.join(format!("scene.{}", extension));
+                save(&original, &converted).unwrap();
+                let back = directory.join("scene.ron");
+                save(&load::<World>(&converted).unwrap(), &back).unwrap();
+                let world: World = load(&back).unwrap();
+                assert_eq!(
+                    serde_json::to_value(&world).unwrap(),
+                    expected,
+                    "{} changed going through {}",
+                    scene,
+                    extension
+                );
+            }
+        }
+        std::fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,584 @@
+use std::{path::Path, sync::Arc};
+
+use cgmath::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::material::present;
+
+// patterns give a value between 0 and 1 at a point, which blends between the a and b textures inside them,
+// so they can be nested as deep as needed with Color and Value at the bottom
+#[derive(Serialize, Deserialize, Clone)]
+pub enum Texture {
+    Color(cgmath::Vector3<f64>),
+    Value(f64),
+    Checker {
+        scale: f64,
+        a: Box<Texture>,
+        b: Box<Texture>,
+    },
+    Stripes {
+        scale: f64,
+        direction: cgmath::Vector3<f64>,
+        a: Box<Texture>,
+        b: Box<Texture>,
+    },
+    Grid {
+        scale: f64,
+        // as a fraction of a cell
+        line_width: f64,
+        line: Box<Texture>,
+        fill: Box<Texture>,
+    },
+    Noise {
+        scale: f64,
+        a: Box<Texture>,
+        b: Box<Texture>,
+    },
+    Fbm {
+        scale: f64,
+        octaves: u32,
+        #[serde(default = "default_lacunarity")]
+        lacunarity: f64,
+        #[serde(default = "default_gain")]
+        gain: f64,
+        a: Box<Texture>,
+        b: Box<Texture>,
+    },
+    Voronoi {
+        scale: f64,
+        a: Box<Texture>,
+        b: Box<Texture>,
+    },
+    Gradient {
+        from: cgmath::Point3<f64>,
+        to: cgmath::Point3<f64>,
+        a: Box<Texture>,
+        b: Box<Texture>,
+    },
+    // a png or hdr file projected onto the object from all three axes
+    Image {
+        // relative to the scene file
+        path: String,
+        #[serde(default = "default_image_scale")]
+        scale: f64,
+        // how quickly the projections fade into each other, higher is a harder edge
+        #[serde(default = "default_sharpness")]
+        sharpness: f64,
+        #[serde(default)]
+        filtering: Filtering,
+        #[serde(skip)]
+        data: Option<Arc<ImageData>>,
+    },
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Default)]
+pub enum Filtering {
+    Nearest,
+    #[default]
+    Bilinear,
+}
+
+// pixels are stored as linear colors, pngs get their srgb curve undone while loading
+pub struct ImageData {
+    width: usize,
+    height: usize,
+    pixels: Vec<cgmath::Vector3<f64>>,
+    // smallest and largest channel average, for working out how far a displacement can reach
+    range: (f64, f64),
+}
+
+fn srgb_to_linear(value: f64) -> f64 {
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+impl ImageData {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let image = image::open(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        let linear = matches!(
+            image,
+            image::DynamicImage::ImageRgb32F(_) | image::DynamicImage::ImageRgba32F(_)
+        );
+        let image = image.to_rgb32f();
+        let pixels = image
+            .pixels()
+            .map(|p| {
+                let color = cgmath::vec3(p[0] as f64, p[1] as f64, p[2] as f64);
+                if linear {
+                    color
+                } else {
+                    color.map(srgb_to_linear)
+                }
+            })
+            .collect::<Vec<_>>();
+        let (width, height) = (image.width() as usize, image.height() as usize);
+
+        let values: Vec<_> = pixels.iter().map(|&p| average(p)).collect();
+        let range = values
+            .iter()
+            .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &v| {
+                (lo.min(v), hi.max(v))
+            });
+        Ok(Self {
+            width,
+            height,
+            pixels,
+            range,
+        })
+    }
+
+    fn pixel(&self, x: i64, y: i64) -> cgmath::Vector3<f64> {
+        let x = x.rem_euclid(self.width as i64) as usize;
+        let y = y.rem_euclid(self.height as i64) as usize;
+        self.pixels[y * self.width + x]
+    }
+
+    // the image repeats, (0, 0) is the top left and (1, 1) the bottom right
+    pub fn sample(&self, u: f64, v: f64, filtering: Filtering) -> cgmath::Vector3<f64> {
+        let x = u * self.width as f64;
+        let y = v * self.height as f64;
+        match filtering {
+            Filtering::Nearest => self.pixel(x.floor() as i64, y.floor() as i64),
+            Filtering::Bilinear => {
+                let (x, y) = (x - 0.5, y - 0.5);
+                let (x0, y0) = (x.floor(), y.floor());
+                let (tx, ty) = (x - x0, y - y0);
+                let (x0, y0) = (x0 as i64, y0 as i64);
+                let top = self.pixel(x0, y0).lerp(self.pixel(x0 + 1, y0), tx);
+                let bottom = self.pixel(x0, y0 + 1).lerp(self.pixel(x0 + 1, y0 + 1), tx);
+                top.lerp(bottom, ty)
+            }
+        }
+    }
+}
+
+fn average(v: cgmath::Vector3<f64>) -> f64 {
+    (v.x + v.y + v.z) / 3.0
+}
+
+// the steepest the improved perlin noise gets, measured and rounded up a bit
+static PERLIN_MAX_SLOPE: f64 = 3.5;
+
+fn default_image_scale() -> f64 {
+    1.0
+}
+
+fn default_sharpness() -> f64 {
+    4.0
+}
+
+fn default_lacunarity() -> f64 {
+    2.0
+}
+
+fn default_gain() -> f64 {
+    0.5
+}
+
+// one texture for each material parameter that can have one, they replace the plain value when set
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(default)]
+pub struct MaterialTextures {
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub color: Option<Texture>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub emitance: Option<Texture>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub metalness: Option<Texture>,
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub roughness: Option<Texture>,
+    // a height map that tilts the shading normal without changing the shape
+    #[serde(with = "present", skip_serializing_if = "Option::is_none")]
+    pub bump: Option<Texture>,
+}
+
+impl MaterialTextures {
+    pub const NONE: MaterialTextures = MaterialTextures {
+        color: None,
+        emitance: None,
+        metalness: None,
+        roughness: None,
+        bump: None,
+    };
+
+    pub fn is_empty(&self) -> bool {
+        self.color.is_none()
+            && self.emitance.is_none()
+            && self.metalness.is_none()
+            && self.roughness.is_none()
+            && self.bump.is_none()
+    }
+
+    // only image projections look at the normal, working it out takes six more distance estimates
+    pub fn needs_normal(&self) -> bool {
+        [
+            &self.color,
+            &self.emitance,
+            &self.metalness,
+            &self.roughness,
+            &self.bump,
+        ]
+        .into_iter()
+        .flatten()
+        .any(Texture::needs_normal)
+    }
+
+    pub fn load_images(&mut self, base: &Path) -> Result<(), String> {
+        for texture in [
+            &mut self.color,
+            &mut self.emitance,
+            &mut self.metalness,
+            &mut self.roughness,
+            &mut self.bump,
+        ]
+        .into_iter()
+        .flatten()
+        {
+            texture.load_images(base)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for MaterialTextures {
+    fn default() -> Self {
+        Self::NONE
+    }
+}
+
+fn hash(x: i64, y: i64, z: i64) -> u64 {
+    let mut h = (x as u64).wrapping_mul(0x9E3779B97F4A7C15)
+        ^ (y as u64).wrapping_mul(0xC2B2AE3D27D4EB4F)
+        ^ (z as u64).wrapping_mul(0x165667B19E3779F9);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xFF51AFD7ED558CCD);
+    h ^= h >> 33;
+    h = h.wrapping_mul(0xC4CEB9FE1A85EC53);
+    h ^ (h >> 33)
+}
+
+fn hash_to_unit(h: u64) -> f64 {
+    (h >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn fade(t: f64) -> f64 {
+    t * t * t * (t * (t * 6.0 - 15.0) + 10.0)
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+// improved perlin noise, gives values roughly between -1 and 1
+pub fn perlin(point: cgmath::Point3<f64>) -> f64 {
+    let cell = point.map(f64::floor);
+    let local = point - cell;
+    let (cx, cy, cz) = (cell.x as i64, cell.y as i64, cell.z as i64);
+
+    let corner = |dx: i64, dy: i64, dz: i64| {
+        // the 12 edge directions of a cube, like the original
+        let gradient = match hash(cx + dx, cy + dy, cz + dz) % 12 {
+            0 => cgmath::vec3(1.0, 1.0, 0.0),
+            1 => cgmath::vec3(-1.0, 1.0, 0.0),
+            2 => cgmath::vec3(1.0, -1.0, 0.0),
+            3 => cgmath::vec3(-1.0, -1.0, 0.0),
+            4 => cgmath::vec3(1.0, 0.0, 1.0),
+            5 => cgmath::vec3(-1.0, 0.0, 1.0),
+            6 => cgmath::vec3(1.0, 0.0, -1.0),
+            7 => cgmath::vec3(-1.0, 0.0, -1.0),
+            8 => cgmath::vec3(0.0, 1.0, 1.0),
+            9 => cgmath::vec3(0.0, -1.0, 1.0),
+            10 => cgmath::vec3(0.0, 1.0, -1.0),
+            _ => cgmath::vec3(0.0, -1.0, -1.0),
+        };
+        gradient.dot(local - cgmath::vec3(dx as f64, dy as f64, dz as f64))
+    };
+
+    let (u, v, w) = (fade(local.x), fade(local.y), fade(local.z));
+    lerp(
+        lerp(
+            lerp(corner(0, 0, 0), corner(1, 0, 0), u),
+            lerp(corner(0, 1, 0), corner(1, 1, 0), u),
+            v,
+        ),
+        lerp(
+            lerp(corner(0, 0, 1), corner(1, 0, 1), u),
+            lerp(corner(0, 1, 1), corner(1, 1, 1), u),
+            v,
+        ),
+        w,
+    )
+}
+
+pub fn fbm(point: cgmath::Point3<f64>, octaves: u32, lacunarity: f64, gain: f64) -> f64 {
+    let mut total = 0.0;
+    let mut amplitude = 1.0;
+    let mut max_total = 0.0;
+    let mut frequency = 1.0;
+    for _ in 0..octaves {
+        total += perlin(point * frequency) * amplitude;
+        max_total += amplitude;
+        amplitude *= gain;
+        frequency *= lacunarity;
+    }
+    if max_total > 0.0 {
+        total / max_total
+    } else {
+        0.0
+    }
+}
+
+// distance to the closest of one random point per cell
+pub fn voronoi(point: cgmath::Point3<f64>) -> f64 {
+    let cell = point.map(f64::floor);
+    let mut closest = f64::INFINITY;
+    for dx in -1..=1 {
+        for dy in -1..=1 {
+            for dz in -1..=1 {
+                let (x, y, z) = (cell.x as i64 + dx, cell.y as i64 + dy, cell.z as i64 + dz);
+                let h = hash(x, y, z);
+                let feature = cgmath::point3(
+                    x as f64 + hash_to_unit(h),
+                    y as f64 + hash_to_unit(hash(h as i64, 1, 0)),
+                    z as f64 + hash_to_unit(hash(h as i64, 2, 0)),
+                );
+                closest = closest.min(point.distance(feature));
+            }
+        }
+    }
+    closest
+}
+
+impl Texture {
+    // normal is the surface normal of the object at point, only the image projection cares about it
+    pub fn evaluate(
+        &self,
+        point: cgmath::Point3<f64>,
+        normal: cgmath::Vector3<f64>,
+    ) -> cgmath::Vector3<f64> {
+        let blend = |a: &Texture, b: &Texture, t: f64| {
+            let t = t.clamp(0.0, 1.0);
+            a.evaluate(point, normal).lerp(b.evaluate(point, normal), t)
+        };
+        match self {
+            Self::Color(color) => *color,
+            Self::Value(value) => cgmath::vec3(*value, *value, *value),
+            Self::Checker { scale, a, b } => {
+                let p = (point * *scale).map(f64::floor);
+                let t = (p.x + p.y + p.z).rem_euclid(2.0);
+                blend(a, b, t)
+            }
+            Self::Stripes {
+                scale,
+                direction,
+                a,
+                b,
+            } => {
+                let along = point.to_vec().dot(direction.normalize()) * scale;
+                blend(a, b, along.floor().rem_euclid(2.0))
+            }
+            Self::Grid {
+                scale,
+                line_width,
+                line,
+                fill,
+            } => {
+                let p = (point * *scale).map(|x| x - x.floor());
+                let half = line_width / 2.0;
+                // a surface that lies exactly on a cell edge, like a floor at y = 0, is all line
+                let on_line = [p.x, p.y, p.z].iter().any(|&x| x < half || x > 1.0 - half);
+                blend(fill, line, if on_line { 1.0 } else { 0.0 })
+            }
+            Self::Noise { scale, a, b } => blend(a, b, perlin(point * *scale) * 0.5 + 0.5),
+            Self::Fbm {
+                scale,
+                octaves,
+                lacunarity,
+                gain,
+                a,
+                b,
+            } => blend(
+                a,
+                b,
+                fbm(point * *scale, *octaves, *lacunarity, *gain) * 0.5 + 0.5,
+            ),
+            Self::Voronoi { scale, a, b } => blend(a, b, voronoi(point * *scale)),
+            Self::Gradient { from, to, a, b } => {
+                let along = to - from;
+                let t = (point - from).dot(along) / along.magnitude2();
+                blend(a, b, t)
+            }
+            Self::Image {
+                scale,
+                sharpness,
+                filtering,
+                data,
+                ..
+            } => {
+                let data = match data {
+                    Some(data) => data,
+                    None => return cgmath::vec3(1.0, 0.0, 1.0),
+                };
+                let p = point * *scale;
+                let weights = normal.map(|x| x.abs().powf(*sharpness));
+                let total = weights.x + weights.y + weights.z;
+                if total <= 0.0 {
+                    return data.sample(p.x, p.y, *filtering);
+                }
+                let weights = weights / total;
+
+                let mut color = cgmath::vec3(0.0, 0.0, 0.0);
+                for (weight, (u, v)) in [
+                    (weights.x, (p.z, p.y)),
+                    (weights.y, (p.x, p.z)),
+                    (weights.z, (p.x, p.y)),
+                ] {
+                    // skipping the projections that barely show saves a good amount of lookups
+                    if weight > 0.001 {
+                        color += data.sample(u, v, *filtering) * weight;
+                    }
+                }
+                color
+            }
+        }
+    }
+
+    // also where the patterns that would only ever give NaN get turned away
+    pub fn load_images(&mut self, base: &Path) -> Result<(), String> {
+        match self {
+            Self::Color(_) | Self::Value(_) => Ok(()),
+            Self::Stripes { direction, .. } if direction.magnitude2() == 0.0 => {
+                Err("Stripes needs a direction that isn't 0".to_string())
+            }
+            Self::Gradient { from, to, .. } if from == to => {
+                Err("Gradient needs from and to to be different points".to_string())
+            }
+            Self::Checker { a, b, .. }
+            | Self::Stripes { a, b, .. }
+            | Self::Noise { a, b, .. }
+            | Self::Fbm { a, b, .. }
+            | Self::Voronoi { a, b, .. }
+            | Self::Gradient { a, b, .. } => {
+                a.load_images(base)?;
+                b.load_images(base)
+            }
+            Self::Grid { line, fill, .. } => {
+                line.load_images(base)?;
+                fill.load_images(base)
+            }
+            Self::Image { path, data, .. } => {
+                // things from included files get their images loaded relative to that file first
+                if data.is_none() {
+                    *data = Some(Arc::new(ImageData::load(&base.join(path))?));
+                }
+                Ok(())
+            }
+        }
+    }
+
+    // single values like roughness just use the average of the channels
+    pub fn evaluate_scalar(&self, point: cgmath::Point3<f64>, normal: cgmath::Vector3<f64>) -> f64 {
+        average(self.evaluate(point, normal))
+    }
+
+    // how much a scalar lookup changes per unit of distance, as the gradient of a central difference
+    pub fn scalar_gradient(
+        &self,
+        point: cgmath::Point3<f64>,
+        normal: cgmath::Vector3<f64>,
+    ) -> cgmath::Vector3<f64> {
+        let e = 0.0005;
+        let at = |offset: cgmath::Vector3<f64>| {
+            self.evaluate_scalar(point + offset, normal)
+                - self.evaluate_scalar(point - offset, normal)
+        };
+        cgmath::vec3(
+            at(cgmath::vec3(e, 0.0, 0.0)),
+            at(cgmath::vec3(0.0, e, 0.0)),
+            at(cgmath::vec3(0.0, 0.0, e)),
+        ) / (2.0 * e)
+    }
+
+    pub fn needs_normal(&self) -> bool {
+        match self {
+            Self::Color(_) | Self::Value(_) => false,
+            Self::Checker { a, b, .. }
+            | Self::Stripes { a, b, .. }
+            | Self::Noise { a, b, .. }
+            | Self::Fbm { a, b, .. }
+            | Self::Voronoi { a, b, .. }
+            | Self::Gradient { a, b, .. } => a.needs_normal() || b.needs_normal(),
+            Self::Grid { line, fill, .. } => line.needs_normal() || fill.needs_normal(),
+            Self::Image { .. } => true,
+        }
+    }
+
+    // smallest and largest scalar value this can give
+    pub fn range(&self) -> (f64, f64) {
+        let join = |a: &Texture, b: &Texture| {
+            let (a, b) = (a.range(), b.range());
+            (a.0.min(b.0), a.1.max(b.1))
+        };
+        match self {
+            Self::Color(color) => (average(*color), average(*color)),
+            Self::Value(value) => (*value, *value),
+            Self::Checker { a, b, .. }
+            | Self::Stripes { a, b, .. }
+            | Self::Noise { a, b, .. }
+            | Self::Fbm { a, b, .. }
+            | Self::Voronoi { a, b, .. }
+            | Self::Gradient { a, b, .. } => join(a, b),
+            Self::Grid { line, fill, .. } => join(line, fill),
+            Self::Image { data, .. } => data.as_ref().map_or((0.0, 1.0), |d| d.range),
+        }
+    }
+
+    // an upper bound on how fast the scalar value can change per unit of distance,
+    // patterns with hard edges don't have one so they give back infinity
+    pub fn max_slope(&self) -> f64 {
+        // lerping between a and b by t can't change faster than the steeper of the two plus t's slope times the gap
+        let blend = |a: &Texture, b: &Texture, t_slope: f64| {
+            let (low, high) = self.range();
+            let spread = high - low;
+            let t_part = if spread > 0.0 { spread * t_slope } else { 0.0 };
+            a.max_slope().max(b.max_slope()) + t_part
+        };
+        match self {
+            Self::Color(_) | Self::Value(_) => 0.0,
+            Self::Checker { a, b, .. } | Self::Stripes { a, b, .. } => blend(a, b, f64::INFINITY),
+            Self::Grid { line, fill, .. } => blend(fill, line, f64::INFINITY),
+            Self::Noise { scale, a, b } => blend(a, b, 0.5 * PERLIN_MAX_SLOPE * scale.abs()),
+            Self::Fbm {
+                scale,
+                octaves,
+                lacunarity,
+                gain,
+                a,
+                b,
+            } => {
+                let (mut amplitude, mut frequency) = (1.0, 1.0);
+                let (mut slope, mut total) = (0.0, 0.0);
+                for _ in 0..*octaves {
+                    slope += amplitude * frequency;
+                    total += amplitude;
+                    amplitude *= gain.abs();
+                    frequency *= lacunarity.abs();
+                }
+                let t_slope = if total > 0.0 {
+                    0.5 * PERLIN_MAX_SLOPE * scale.abs() * slope / total
+                } else {
+                    0.0
+                };
+                blend(a, b, t_slope)
+            }
+            Self::Voronoi { scale, a, b } => blend(a, b, scale.abs()),
+            Self::Gradient { from, to, a, b } => blend(a, b, 1.0 / from.distance(*to)),
+            // the projections get blended by the normal, which turns as fast as the object curves,
+            // so there's no telling how steep the blend gets from the picture alone
+            Self::Image { .. } => f64::INFINITY,
+        }
+    }
+}
@@ -0,0 +1,144 @@
+use cgmath::prelude::*;
+use serde::{Deserialize, Serialize};
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ToneMapOperator {
+    Linear,
+    Reinhard,
+    ExtendedReinhard,
+    Aces,
+    AgX,
+}
+
+impl ToneMapOperator {
+    pub const ALL: [ToneMapOperator; 5] = [
+        Self::Linear,
+        Self::Reinhard,
+        Self::ExtendedReinhard,
+        Self::Aces,
+        Self::AgX,
+    ];
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.to_ascii_lowercase().replace(['-', '_'], "").as_str() {
+            "linear" => Some(Self::Linear),
+            "reinhard" => Some(Self::Reinhard),
+            "extendedreinhard" => Some(Self::ExtendedReinhard),
+            "aces" => Some(Self::Aces),
+            "agx" => Some(Self::AgX),
+            _ => None,
+        }
+    }
+
+    pub fn next(self) -> Self {
+        let idx = Self::ALL.iter().position(|&o| o == self).unwrap_or(0);
+        Self::ALL[(idx + 1) % Self::ALL.len()]
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default)]
+pub struct ToneMapping {
+    pub operator: ToneMapOperator,
+    // in stops, 0 leaves the image as is
+    pub exposure: f64,
+    // luminance that maps to pure white, only used by ExtendedReinhard
+    pub white_point: f64,
+}
+
+impl Default for ToneMapping {
+    fn default() -> Self {
+        Self {
+            operator: ToneMapOperator::Reinhard,
+            exposure: 0.0,
+            white_point: 4.0,
+        }
+    }
+}
+
+fn luminance(color: cgmath::Vector3<f64>) -> f64 {
+    color.dot(cgmath::vec3(0.2126, 0.7152, 0.0722))
+}
+
+fn aces_fitted(color: cgmath::Vector3<f64>) -> cgmath::Vector3<f64> {
+    // Stephen Hill's fit of the ACES RRT + sRGB ODT, matrices are column major
+    let input = cgmath::Matrix3::new(
+        0.59719, 0.07600, 0.02840, 0.35458, 0.90834, 0.13383, 0.04823, 0.01566, 0.83777,
+    );
+    let output = cgmath::Matrix3::new(
+        1.60475, -0.10208, -0.00327, -0.53108, 1.10813, -0.07276, -0.07367, -0.00605, 1.07602,
+    );
+    let v = input * color;
+    let a = v.map(|x| x * (x + 0.0245786) - 0.000090537);
+    let b = v.map(|x| x * (0.983729 * x + 0.4329510) + 0.238081);
+    output * a.div_element_wise(b)
+}
+
+fn agx(color: cgmath::Vector3<f64>) -> cgmath::Vector3<f64> {
+    // the minimal AgX approximation with the default look, returns linear values
+    let inset = cgmath::Matrix3::new(
+        0.842479062253094,
+        0.0423282422610123,
+        0.0423756549057051,
+        0.0784335999999992,
+        0.878468636469772,
+        0.0784336,
+        0.0792237451477643,
+        0.0791661274605434,
+        0.879142973793104,
+    );
+    let outset = cgmath::Matrix3::new(
+        1.19687900512017,
+        -0.0528968517574562,
+        -0.0529716355144438,
+        -0.0980208811401368,
+        1.15190312990417,
+        -0.0980434501171241,
+        -0.0990297440797205,
+        -0.0989611768448433,
+        1.15107367264116,
+    );
+    let min_ev = -12.47393;
+    let max_ev = 4.026069;
+
+    let v = (inset * color).map(|x| {
+        let x = (x.max(1e-10).log2().clamp(min_ev, max_ev) - min_ev) / (max_ev - min_ev);
+        let x2 = x * x;
+        let x4 = x2 * x2;
+        15.5 * x4 * x2 - 40.14 * x4 * x + 31.96 * x4 - 6.868 * x2 * x + 0.4298 * x2 + 0.1191 * x
+            - 0.00232
+    });
+    (outset * v).map(|x| x.max(0.0).powf(2.2))
+}
+
+pub fn linear_to_srgb(value: f64) -> f64 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+impl ToneMapping {
+    // takes a linear hdr color and gives back a display ready srgb color in 0..1
+    pub fn map(&self, color: cgmath::Vector3<f64>) -> cgmath::Vector3<f64> {
+        let color = color.map(|x| x.max(0.0)) * self.exposure.exp2();
+        let mapped = match self.operator {
+            ToneMapOperator::Linear => color,
+            ToneMapOperator::Reinhard => color.map(|x| x / (x + 1.0)),
+            ToneMapOperator::ExtendedReinhard => {
+                let l = luminance(color);
+                if l <= 0.0 {
+                    color
+                } else {
+                    let white2 = self.white_point * self.white_point;
+                    let new_l = l * (1.0 + l / white2) / (1.0 + l);
+                    color * (new_l / l)
+                }
+            }
+            ToneMapOperator::Aces => aces_fitted(color),
+            ToneMapOperator::AgX => agx(color),
+        };
+        mapped.map(|x| linear_to_srgb(x.clamp(0.0, 1.0)))
+    }
+}